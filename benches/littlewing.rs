@@ -12,6 +12,7 @@ use littlewing::game::Game;
 use littlewing::piece_move_generator::PieceMoveGenerator;
 use littlewing::piece_move_notation::PieceMoveNotation;
 use littlewing::search::Search;
+use littlewing::search_limits::SearchLimits;
 
 #[bench]
 fn bench_next_move(b: &mut Bencher) {
@@ -86,7 +87,7 @@ fn bench_search(b: &mut Bencher) {
     let mut game = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
 
     b.iter(|| {
-        game.search(1..5)
+        game.search(SearchLimits { depth: Some(4), ..SearchLimits::new() })
     })
 }
 
@@ -119,7 +120,7 @@ fn bench_tt_16mb_get(b: &mut Bencher) {
     let mut game = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
     let m = game.move_from_lan("e2e4");
     game.tt_resize(16 << 20); // 16 MB
-    game.search(1..5);
+    game.search(SearchLimits { depth: Some(4), ..SearchLimits::new() });
     game.make_move(m);
     let hash = game.positions.top().hash;
     b.iter(|| {
@@ -136,7 +137,7 @@ fn bench_tt_256mb_get(b: &mut Bencher) {
     let mut game = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
     let m = game.move_from_lan("e2e4");
     game.tt_resize(256 << 20); // 256 MB
-    game.search(1..5);
+    game.search(SearchLimits { depth: Some(4), ..SearchLimits::new() });
     game.make_move(m);
     let hash = game.positions.top().hash;
     b.iter(|| {
@@ -1,7 +1,10 @@
 use std::prelude::v1::*;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::cmp;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
+use crate::search_handle::SearchHandle;
+
 #[cfg(feature = "std")]
 fn default_system_time() -> u128 {
     use std::time::SystemTime;
@@ -13,16 +16,42 @@ fn default_system_time() -> u128 {
     0 // NOTE: Must be overrided in Clock by external crates
 }
 
+// Number of moves expected before the end of the game in sudden death, that
+// decreases with each move played until a minimum
+const SUDDEN_DEATH_MOVES: u16 = 40;
+const SUDDEN_DEATH_MIN_MOVES: u16 = 20;
+
+// Maximum ratio between the hard limit and the time allocated to a move
+const HARD_LIMIT_RATIO: u64 = 3;
+
+// Expected ratio between the times of two consecutive iterations
+const ITERATION_TIME_RATIO: u64 = 2;
+
+// A stage of a time control giving time to play a number of moves, or the
+// rest of the game when the number is zero, with an increment after each move
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stage {
+    moves: u16,
+    time: u64,
+    increment: u64,
+}
+
 #[derive(Clone)]
 pub struct Clock {
     pub system_time: Arc<dyn Fn() -> u128 + Send + Sync + 'static>,
     pub polling_nodes_count: u64,
     pub started_at: u128,
-    moves_level: u16,
+    stages: Vec<Stage>, // The last stage is repeated
+    stage: usize,
     moves_remaining: u16,
     time_remaining: u64,
+    move_overhead: u64,
+    time_factor: u64,
     last_nodes_count: u64,
+    nodes_limit: u64,
+    nodes_count: Arc<AtomicU64>, // Shared between search threads
     is_finished: Arc<AtomicBool>,
+    is_pondering: Arc<AtomicBool>,
     is_level: bool // TODO: find a better name
 }
 
@@ -33,34 +62,102 @@ impl Clock {
             system_time: system_time,
             polling_nodes_count: 100,
             started_at: 0,
-            moves_level: moves,
-            moves_remaining: if moves > 0 { moves } else { 20 },
+            stages: vec![Stage { moves, time, increment: 0 }],
+            stage: 0,
+            moves_remaining: if moves > 0 { moves } else { SUDDEN_DEATH_MIN_MOVES },
             time_remaining: time,
+            move_overhead: 0,
+            time_factor: 100,
             last_nodes_count: 0,
+            nodes_limit: u64::MAX,
+            nodes_count: Arc::new(AtomicU64::new(0)),
             is_finished: Arc::new(AtomicBool::new(false)),
+            is_pondering: Arc::new(AtomicBool::new(false)),
             is_level: true
         }
     }
 
     pub fn start(&mut self, ply: usize) {
         self.is_finished.store(false, Ordering::Relaxed);
+        self.nodes_count.store(0, Ordering::Relaxed);
         self.last_nodes_count = 0;
         self.started_at = (self.system_time)();
+        self.time_factor = 100;
 
         // The UCI protocol gives the number of remaining moves before each
         // search but XBoard doesn't so we need to calculate it based on moves
-        // history and the level command.
-        if self.is_level && self.moves_level > 0 {
+        // history and the stages of the time control.
+        if self.is_level {
             assert!(ply > 0);
-            let moves_done = (((ply - 1) / 2) as u16) % self.moves_level;
-            self.moves_remaining = self.moves_level - moves_done;
+            let mut moves_done = ((ply - 1) / 2) as u16;
+            self.stage = 0;
+            while self.stage + 1 < self.stages.len() && moves_done >= self.stages[self.stage].moves {
+                moves_done -= self.stages[self.stage].moves;
+                self.stage += 1;
+            }
+            let moves = self.stages[self.stage].moves;
+            self.moves_remaining = if moves > 0 {
+                moves - moves_done % moves
+            } else {
+                cmp::max(SUDDEN_DEATH_MOVES.saturating_sub(moves_done), SUDDEN_DEATH_MIN_MOVES)
+            };
+        }
+    }
+
+    /// Create a `Clock` from a time control in PGN format given in seconds,
+    /// with stages of moves to play in a given time, or sudden death, and
+    /// optional increments separated by colons, like `40/5400+30:1800+30`
+    pub fn from_time_control(time_control: &str) -> Result<Clock, String> {
+        let mut clock = Clock::new(0, 0);
+        clock.stages.clear();
+        let parse_time = |s: &str| match s.parse::<f64>() {
+            Ok(t) if t >= 0.0 => Ok((t * 1000.0).round() as u64),
+            _ => Err(format!("invalid time '{}'", s)),
+        };
+        let n = time_control.split(':').count();
+        for (i, stage) in time_control.split(':').enumerate() {
+            let (stage, increment) = match stage.split_once('+') {
+                Some((stage, increment)) => (stage, parse_time(increment)?),
+                None => (stage, 0),
+            };
+            let (moves, time) = match stage.split_once('/') {
+                Some((moves, time)) => match moves.parse::<u16>() {
+                    Ok(moves) if moves > 0 => (moves, parse_time(time)?),
+                    _ => return Err(format!("invalid moves '{}'", moves)),
+                },
+                None => (0, parse_time(stage)?),
+            };
+            if moves == 0 && i + 1 < n {
+                return Err("sudden death must be the last stage".into());
+            }
+            clock.stages.push(Stage { moves, time, increment });
         }
+        clock.time_remaining = clock.stages[0].time;
+        clock.moves_remaining = match clock.stages[0].moves {
+            0 => SUDDEN_DEATH_MIN_MOVES,
+            moves => moves,
+        };
+        Ok(clock)
     }
 
     pub fn stop(&mut self) {
         self.is_finished.store(true, Ordering::Relaxed);
     }
 
+    /// Share the stop signal and the nodes count of the clock with a search
+    /// handle, keeping a stop requested before the start of the clock
+    pub(crate) fn attach(&mut self, handle: &SearchHandle) {
+        self.is_finished = handle.is_stopped.clone();
+        self.nodes_count = handle.nodes_count.clone();
+    }
+
+    /// Stop sharing the stop signal and the nodes count of the clock with a
+    /// search handle, to start the clock without clearing a stop request
+    pub(crate) fn detach(&mut self) {
+        self.is_finished = Arc::new(AtomicBool::new(false));
+        self.nodes_count = Arc::new(AtomicU64::new(0));
+    }
+
     pub fn disable_level(&mut self) {
         self.is_level = false;
     }
@@ -69,19 +166,120 @@ impl Clock {
         self.time_remaining = time;
     }
 
+    /// Set the time added after each move in every stage of the time control
+    pub fn set_increment(&mut self, time: u64) {
+        for stage in self.stages.iter_mut() {
+            stage.increment = time;
+        }
+    }
+
+    /// Set the time lost to communicate each move that should not be used
+    /// to search
+    pub fn set_move_overhead(&mut self, time: u64) {
+        self.move_overhead = time;
+    }
+
+    /// Allocate exactly the given time to the next move
+    pub fn set_movetime(&mut self, time: u64) {
+        self.is_level = false;
+        self.stages = vec![Stage { moves: 1, time, increment: 0 }];
+        self.stage = 0;
+        self.moves_remaining = 1;
+        self.time_remaining = time;
+    }
+
+    /// Stop the search after the given number of nodes in all threads
+    pub fn set_nodes_limit(&mut self, nodes: u64) {
+        self.nodes_limit = nodes;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_finished.load(Ordering::Relaxed)
+    }
+
+    /// Search on the opponent's time, without time limit until `ponderhit`
+    pub fn ponder(&mut self) {
+        self.is_pondering.store(true, Ordering::Relaxed);
+    }
+
+    /// Switch from pondering to normal time management
+    pub fn ponderhit(&mut self) {
+        self.is_pondering.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.is_pondering.load(Ordering::Relaxed)
+    }
+
     pub fn allocated_time(&self) -> u64 {
-        self.time_remaining / self.moves_remaining as u64
+        let moves = cmp::max(self.moves_remaining, 1) as u64;
+        let increment = self.stages[self.stage].increment;
+        let time = self.time_remaining.saturating_sub(self.move_overhead);
+
+        // Share the remaining time and the increments to come between the
+        // remaining moves, but keep enough time for the next moves.
+        let allocated = time.saturating_add(increment.saturating_mul(moves - 1)) / moves;
+        let max = if moves > 1 { time / 2 } else { time };
+        cmp::min(allocated, max)
+    }
+
+    /// Get the time after which the search should stop between iterations,
+    /// given by the time allocated to the move scaled by the time factor
+    pub fn soft_limit(&self) -> u64 {
+        let soft_limit = self.allocated_time().saturating_mul(self.time_factor) / 100;
+        cmp::min(soft_limit, self.hard_limit())
+    }
+
+    /// Get the time after which the search must stop in any case
+    pub fn hard_limit(&self) -> u64 {
+        let allocated_time = self.allocated_time();
+        if self.moves_remaining <= 1 {
+            return allocated_time;
+        }
+        let time = self.time_remaining.saturating_sub(self.move_overhead);
+        cmp::min(allocated_time.saturating_mul(HARD_LIMIT_RATIO), cmp::max(time / 2, allocated_time))
+    }
+
+    /// Scale the soft limit by the given percentage of the time allocated to
+    /// the move
+    pub fn set_time_factor(&mut self, percent: u64) {
+        self.time_factor = percent;
+    }
+
+    /// Check if the search can start a new iteration after one that took the
+    /// given time, before reaching the soft limit and if it can be expected
+    /// to finish before the hard limit
+    pub fn can_start_iteration(&self, iteration_time: u64) -> bool {
+        if self.is_pondering() {
+            return true;
+        }
+        let elapsed_time = self.elapsed_time();
+        let expected_time = iteration_time.saturating_mul(ITERATION_TIME_RATIO);
+        elapsed_time < self.soft_limit() && elapsed_time.saturating_add(expected_time) < self.hard_limit()
     }
 
     pub fn elapsed_time(&self) -> u64 {
         ((self.system_time)() - self.started_at) as u64
     }
 
+    /// Get the number of nodes searched by all the threads sharing this
+    /// clock, given the local number of nodes searched by the current thread
+    pub fn total_nodes_count(&self, nodes_count: u64) -> u64 {
+        self.nodes_count.load(Ordering::Relaxed) + nodes_count - self.last_nodes_count
+    }
+
+    /// Add the nodes searched by the current thread since the last call to
+    /// the number of nodes shared by all the threads
+    pub fn add_nodes_count(&mut self, nodes_count: u64) {
+        self.nodes_count.fetch_add(nodes_count - self.last_nodes_count, Ordering::Relaxed);
+        self.last_nodes_count = nodes_count;
+    }
+
     pub fn poll(&mut self, nodes_count: u64) -> bool {
         // We do the real computation only every `polling_nodes_count` nodes
         // TODO: do we need this?
         if nodes_count - self.last_nodes_count > self.polling_nodes_count {
-            self.last_nodes_count = nodes_count;
+            self.add_nodes_count(nodes_count);
 
             // A certain amount of time pass between two polls,
             // and after the end of the search.
@@ -89,7 +287,14 @@ impl Clock {
             let time_to_play = 25;
             let delta = time_between_polls + time_to_play;
 
-            if delta + self.elapsed_time() > self.allocated_time() {
+            if self.is_pondering() {
+                // The time allocated to the move starts at `ponderhit`
+                self.started_at = (self.system_time)();
+            } else if delta + self.elapsed_time() > self.hard_limit() {
+                self.is_finished.store(true, Ordering::Relaxed);
+            }
+
+            if self.nodes_count.load(Ordering::Relaxed) >= self.nodes_limit {
                 self.is_finished.store(true, Ordering::Relaxed);
             }
         }
@@ -97,3 +302,87 @@ impl Clock {
         self.is_finished.load(Ordering::Relaxed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocated_time() {
+        // 40 moves in 5 minutes
+        let mut clock = Clock::new(40, 5 * 60 * 1000);
+        clock.start(1);
+        assert_eq!(clock.allocated_time(), 7500);
+        clock.start(2 * 39 + 1);
+        assert_eq!(clock.allocated_time(), 5 * 60 * 1000);
+        clock.start(2 * 40 + 1);
+        assert_eq!(clock.allocated_time(), 7500);
+
+        // Sudden death in 5 minutes with 3 seconds of increment
+        let mut clock = Clock::new(0, 5 * 60 * 1000);
+        clock.set_increment(3000);
+        clock.start(1);
+        assert_eq!(clock.allocated_time(), 7500 + 3000 * 39 / 40);
+        clock.start(2 * 30 + 1);
+        assert_eq!(clock.allocated_time(), 15000 + 3000 * 19 / 20);
+
+        // The increment doesn't allow to use most of the remaining time
+        clock.set_time(1000);
+        assert_eq!(clock.allocated_time(), 500);
+
+        // Move overhead
+        clock.set_move_overhead(100);
+        assert_eq!(clock.allocated_time(), 450);
+
+        let mut clock = Clock::new(0, 0);
+        clock.set_movetime(1000);
+        clock.set_move_overhead(100);
+        clock.start(1);
+        assert_eq!(clock.allocated_time(), 900);
+    }
+
+    #[test]
+    fn test_time_limits() {
+        let mut clock = Clock::new(40, 5 * 60 * 1000);
+        clock.system_time = Arc::new(|| 0);
+        clock.start(1);
+        assert_eq!(clock.soft_limit(), 7500);
+        assert_eq!(clock.hard_limit(), 3 * 7500);
+        assert!(clock.can_start_iteration(1000));
+        assert!(!clock.can_start_iteration(12000));
+
+        clock.set_time_factor(50);
+        assert_eq!(clock.soft_limit(), 3750);
+        clock.set_time_factor(500);
+        assert_eq!(clock.soft_limit(), 3 * 7500);
+
+        // The time of a single move can't be extended
+        clock.set_movetime(1000);
+        assert_eq!(clock.soft_limit(), 1000);
+        assert_eq!(clock.hard_limit(), 1000);
+        assert!(!clock.can_start_iteration(500));
+    }
+
+    #[test]
+    fn test_from_time_control() {
+        // 40 moves in 90 minutes then 30 minutes for the rest of the game
+        // with 30 seconds of increment from the start
+        let mut clock = Clock::from_time_control("40/5400+30:1800+30").unwrap();
+        clock.start(1);
+        assert_eq!(clock.allocated_time(), (5400 * 1000 + 39 * 30 * 1000) / 40);
+        clock.start(2 * 39 + 1);
+        assert_eq!(clock.moves_remaining, 1);
+        clock.start(2 * 40 + 1);
+        assert_eq!(clock.stage, 1);
+        assert_eq!(clock.moves_remaining, 40);
+
+        let mut clock = Clock::from_time_control("300+2.5").unwrap();
+        clock.start(1);
+        assert_eq!(clock.stages, vec![Stage { moves: 0, time: 300000, increment: 2500 }]);
+
+        assert!(Clock::from_time_control("1800:40/5400").is_err());
+        assert!(Clock::from_time_control("0/300").is_err());
+        assert!(Clock::from_time_control("40/").is_err());
+        assert!(Clock::from_time_control("").is_err());
+    }
+}
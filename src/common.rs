@@ -163,6 +163,7 @@ pub const MAX_MOVES: usize = 256;
 pub const MAX_KILLERS: usize = 2;
 
 pub const TT_SIZE: usize = 8 << 20; // 8 Mb
+pub const PAWN_HASH_SIZE: usize = 1 << 20; // 1 Mb
 
 pub const XSHIFTS: [Shift; 2] = [LEFT, RIGHT];
 pub const YSHIFTS: [Shift; 2] = [UP, DOWN];
@@ -8,10 +8,10 @@ use crate::common::*;
 use crate::attack::Attack;
 use crate::attack::piece_attacks;
 use crate::bitboard::{Bitboard, BitboardExt, BitboardIterator};
-use crate::bitboard::filefill;
+use crate::bitboard::{upfill, downfill, filefill};
 use crate::game::Game;
 use crate::piece_move::PieceMove;
-use crate::piece_square_table::PST;
+use crate::eval_params::EvalParams;
 
 pub const PAWN_VALUE:       Score =   100;
 pub const KNIGHT_VALUE:     Score =   350;
@@ -20,12 +20,17 @@ pub const ROOK_VALUE:       Score =   500;
 pub const QUEEN_VALUE:      Score =  1000; // R + B + P + bonus bishop pair
 pub const KING_VALUE:       Score = 10000;
 
-const BONUS_BISHOP_PAIR:    Score =    50;
+/// Game phase with all the pieces on the board
+pub const MAX_PHASE: Score = 24;
+
+// Contribution of each piece to the game phase
+const PHASE_WEIGHTS: [Score; 7] = [0, 0, 1, 0, 1, 2, 4]; // By piece kind >> 1
+
+//const BONUS_BISHOP_PAIR:    Score =    50;
 //const BONUS_HALF_OPEN_FILE: Score =     5;
 //const BONUS_KNIGHT_PAWNS:   Score =     5;
 //const BONUS_ROOK_OPEN_FILE: Score =    20;
 //const BONUS_ROOK_PAWNS:     Score =     5;
-//const MALUS_DOUBLED_PAWN:   Score =   -10;
 
 lazy_static! {
     static ref PIECE_VALUES: [Score; 14] = {
@@ -55,12 +60,18 @@ pub trait Eval {
     /// Evaluate material at the current position for the given side
     fn eval_material(&self, c: Color) -> Score;
 
+    /// Get the game phase from the non-pawn material, going from
+    /// `MAX_PHASE` in the opening to 0 in the ending
+    fn phase(&self) -> Score;
+
     /// Static Exchange Evaluation
     fn see(&self, capture: PieceMove) -> Score;
 }
 
 trait EvalExt {
     fn eval_ending(&self, c: Color) -> Option<Score>;
+    fn eval_pawns(&self) -> [Score; 2];
+    fn eval_king_safety(&self, c: Color, attackers: Score, weight: Score, occupied: Bitboard) -> Score;
     fn lvp(&self, side: Color, attacks: Bitboard, occupied: Bitboard) -> Square;
 }
 
@@ -74,58 +85,94 @@ impl Eval for Game {
             return score;
         }
 
-        let mut material = [0; 2];
-        let mut mobility = [0; 2];
-        let mut position = [[0; 2]; 2]; // Opening and ending phases
+        // Scores of each side in opening and ending phases
+        let mut material = [[0; 2]; 2];
+        let mut mobility = [[0; 2]; 2];
+        let mut position = [[0; 2]; 2];
+        let mut king_attackers = [0; 2];
+        let mut king_attacks_weight = [0; 2];
 
+        let king_zones = [
+            piece_attacks(KING, self.bitboard(WHITE | KING).scan() as Square, occupied),
+            piece_attacks(KING, self.bitboard(BLACK | KING).scan() as Square, occupied),
+        ];
+
+        let params = &self.eval_params;
         for &c in &COLORS {
-            for &p in &PIECES {
+            for (i, &p) in PIECES.iter().enumerate() {
                 let piece = c | p;
                 let mut pieces = self.bitboards[piece as usize];
                 let n = pieces.count() as Score;
-                material[c as usize] += n * PIECE_VALUES[piece as usize];
+                if p != KING {
+                    material[c as usize][0] += n * params.material[i][0];
+                    material[c as usize][1] += n * params.material[i][1];
+                }
                 if p == BISHOP && n > 1 { // FIXME: Slows eval from 1250ns to 1350ns
-                    material[c as usize] += BONUS_BISHOP_PAIR;
+                    material[c as usize][0] += params.bishop_pair[0];
+                    material[c as usize][1] += params.bishop_pair[1];
                 }
                 while let Some(square) = pieces.next() {
                     let targets = piece_attacks(piece, square, occupied);
-                    mobility[c as usize] += targets.count() as Score;
-                    position[c as usize][0] += PST[piece as usize][square as usize][0];
-                    position[c as usize][1] += PST[piece as usize][square as usize][1];
+                    let n = targets.count() as Score;
+                    mobility[c as usize][0] += n * params.mobility[0];
+                    mobility[c as usize][1] += n * params.mobility[1];
+                    if p != PAWN && p != KING {
+                        let zone_targets = targets & king_zones[(c ^ 1) as usize];
+                        if zone_targets > 0 {
+                            king_attackers[c as usize] += 1;
+                            king_attacks_weight[c as usize] += params.king_attack_weights[i - 1] * zone_targets.count() as Score;
+                        }
+                    }
+                    let s = square.flip(c ^ 1) as usize;
+                    position[c as usize][0] += params.pst_opening[i][s];
+                    position[c as usize][1] += params.pst_ending[i][s];
                 }
             }
         }
 
-        let mut position_score = 0;
-        let mut material_score = 0;
-        let mut mobility_score = 0;
         let c = side as usize;
 
         // Linear interpolation between opening and ending scores
-        // based on the number of pieces on the board
-        let x0 = 32; // Max
-        let x1 = 2; // Min
-        let x = occupied.count() as Score; // Current
+        // based on the game phase
+        let phase = self.phase() as i32;
+        let taper = |y: [Score; 2]| {
+            ((y[0] as i32 * phase + y[1] as i32 * (MAX_PHASE as i32 - phase)) / MAX_PHASE as i32) as Score
+        };
 
-        let y0 = position[c][0];
-        let y1 = position[c][1];
-        position_score += (y0 * (x1 - x) + y1 * (x - x0)) / (x1 - x0);
-        material_score += material[c];
-        mobility_score += mobility[c];
+        let material_score = taper(material[c]) - taper(material[c ^ 1]);
+        let position_score = taper(position[c]) - taper(position[c ^ 1]);
+        let mobility_score = taper(mobility[c]) - taper(mobility[c ^ 1]);
 
-        let y0 = position[c ^ 1][0];
-        let y1 = position[c ^ 1][1];
-        position_score -= (y0 * (x1 - x) + y1 * (x - x0)) / (x1 - x0);
-        material_score -= material[c ^ 1];
-        mobility_score -= mobility[c ^ 1];
+        // Pawn structure scores are relative to white
+        let pawns_score = taper(self.eval_pawns()) * if side == WHITE { 1 } else { -1 };
 
-        let score = position_score + material_score + mobility_score;
+        // King safety only matters in opening phase
+        let king_score =
+            taper([self.eval_king_safety(side, king_attackers[c ^ 1], king_attacks_weight[c ^ 1], occupied), 0]) -
+            taper([self.eval_king_safety(side ^ 1, king_attackers[c], king_attacks_weight[c], occupied), 0]);
+
+        let score = position_score + material_score + mobility_score + pawns_score + king_score;
 
         if self.is_eval_verbose {
-            println!("material: {:>5.2}", 0.01 * material_score as f64);
-            println!("position: {:>5.2}", 0.01 * position_score as f64);
-            println!("mobility: {:>5.2}", 0.01 * mobility_score as f64);
-            println!("total:    {:>5.2}", 0.01 * score as f64);
+            let pawns = [
+                PawnStructure::new(params, self.bitboards[(side | PAWN) as usize], self.bitboards[(side ^ 1 | PAWN) as usize], side),
+                PawnStructure::new(params, self.bitboards[(side ^ 1 | PAWN) as usize], self.bitboards[(side | PAWN) as usize], side ^ 1),
+            ];
+            let term = |f: fn(&PawnStructure) -> [Score; 2]| {
+                0.01 * (taper(f(&pawns[0])) - taper(f(&pawns[1]))) as f64
+            };
+            println!("phase:     {:>5}", phase);
+            println!("material:  {:>5.2}", 0.01 * material_score as f64);
+            println!("position:  {:>5.2}", 0.01 * position_score as f64);
+            println!("mobility:  {:>5.2}", 0.01 * mobility_score as f64);
+            println!("pawns:     {:>5.2}", 0.01 * pawns_score as f64);
+            println!("doubled:   {:>5.2}", term(|p| p.doubled));
+            println!("isolated:  {:>5.2}", term(|p| p.isolated));
+            println!("backward:  {:>5.2}", term(|p| p.backward));
+            println!("connected: {:>5.2}", term(|p| p.connected));
+            println!("passed:    {:>5.2}", term(|p| p.passed));
+            println!("king:      {:>5.2}", 0.01 * king_score as f64);
+            println!("total:     {:>5.2}", 0.01 * score as f64);
         }
 
         score
@@ -157,9 +204,6 @@ impl Eval for Game {
             match p { // FIXME: Slows eval from 65 to 130ns
                 PAWN => {
                     pawns_count = n;
-
-                    let pawns_files_count = (filefill(pieces) & RANK_1).count() as Score;
-                    score += (pawns_count - pawns_files_count) * MALUS_DOUBLED_PAWN;
                 },
                 KNIGHT => {
                     score += n * pawns_count * BONUS_KNIGHT_PAWNS;
@@ -182,6 +226,15 @@ impl Eval for Game {
         score
     }
 
+    fn phase(&self) -> Score {
+        let mut phase = 0;
+        for &p in &[KNIGHT, BISHOP, ROOK, QUEEN] {
+            let n = (self.bitboard(WHITE | p) | self.bitboard(BLACK | p)).count() as Score;
+            phase += n * PHASE_WEIGHTS[(p >> 1) as usize];
+        }
+        cmp::min(phase, MAX_PHASE) // Promotions could make it bigger
+    }
+
     fn see(&self, capture: PieceMove) -> Score {
         let mut occupied = self.bitboard(WHITE) | self.bitboard(BLACK);
         let mut sq = capture.from();
@@ -240,6 +293,86 @@ impl EvalExt for Game {
         None
     }
 
+    // Get the opening and ending scores of the pawn structure relative to
+    // white, from the pawn hash table when possible
+    fn eval_pawns(&self) -> [Score; 2] {
+        let hash = self.positions.top().pawn_hash;
+        if let Some(score) = self.pawn_table.get(hash) {
+            return score;
+        }
+
+        let white_pawns = self.bitboards[(WHITE | PAWN) as usize];
+        let black_pawns = self.bitboards[(BLACK | PAWN) as usize];
+        let white = PawnStructure::new(&self.eval_params, white_pawns, black_pawns, WHITE).total();
+        let black = PawnStructure::new(&self.eval_params, black_pawns, white_pawns, BLACK).total();
+        let score = [white[0] - black[0], white[1] - black[1]];
+
+        self.pawn_table.set(hash, score);
+        score
+    }
+
+    // Get the king safety score of the given side from the number of
+    // pieces attacking its zone and the weight of their attacks, the pawns
+    // in front of the king, and the open files around it
+    fn eval_king_safety(&self, c: Color, attackers: Score, weight: Score, occupied: Bitboard) -> Score {
+        let king = self.bitboard(c | KING).scan() as Square;
+        let pawns = self.bitboards[(c | PAWN) as usize];
+        let opponent_pawns = self.bitboards[(c ^ 1 | PAWN) as usize];
+        let params = &self.eval_params;
+        let mut score = 0;
+
+        // A single attacker is not a threat
+        if attackers > 1 {
+            let i = cmp::min(attackers as usize, params.king_attackers_factors.len() - 1);
+            score -= weight * params.king_attackers_factors[i] / 100;
+
+            // Squares of the zone attacked by the opponent and only
+            // defended by the king
+            let defenders = self.bitboard(c) & !self.bitboard(c | KING);
+            let mut zone = piece_attacks(KING, king, occupied);
+            while let Some(square) = zone.next() {
+                let attacks = self.attacks_to(square, occupied);
+                if attacks & self.bitboard(c ^ 1) > 0 && attacks & defenders == 0 {
+                    score += params.weak_king_square;
+                }
+            }
+        }
+
+        let open_files = open_files(pawns, opponent_pawns);
+        let half_open_files = half_open_files(pawns, opponent_pawns);
+        let rank = king.flip(c).rank();
+        let file = king.file() as usize;
+        for &f in &FILES[file.saturating_sub(1)..cmp::min(file + 2, 8)] {
+            if open_files & f > 0 {
+                score += params.open_king_file;
+            } else if half_open_files & f > 0 {
+                score += params.half_open_king_file;
+            }
+
+            // Distance to the king of the closest pawns in front of it
+            let distance = |mut bb: Bitboard| {
+                let mut d = 0;
+                while let Some(square) = bb.next() {
+                    let r = square.flip(c).rank();
+                    if r > rank && (d == 0 || r - rank < d) {
+                        d = r - rank;
+                    }
+                }
+                d as usize
+            };
+
+            let shield = distance(pawns & f);
+            score += params.pawn_shield[cmp::min(shield, params.pawn_shield.len() - 1)];
+
+            let storm = distance(opponent_pawns & f);
+            if storm < params.pawn_storm.len() {
+                score += params.pawn_storm[storm];
+            }
+        }
+
+        score
+    }
+
     // Get square of least valuable piece
     fn lvp(&self, side: Color, attacks: Bitboard, occupied: Bitboard) -> Square {
         for p in &PIECES {
@@ -256,17 +389,97 @@ impl EvalExt for Game {
     }
 }
 
+// Pawn structure terms of a side in opening and ending phases
+struct PawnStructure {
+    doubled: [Score; 2],
+    isolated: [Score; 2],
+    backward: [Score; 2],
+    connected: [Score; 2],
+    passed: [Score; 2],
+}
+
+impl PawnStructure {
+    fn new(params: &EvalParams, pawns: Bitboard, opponent_pawns: Bitboard, side: Color) -> PawnStructure {
+        // Flip the board for black to look at the pawns from the side of white
+        let (pawns, opponent_pawns) = if side == WHITE {
+            (pawns, opponent_pawns)
+        } else {
+            (pawns.swap_bytes(), opponent_pawns.swap_bytes())
+        };
+
+        let attacks = east(pawns << 8) | west(pawns << 8);
+        let opponent_attacks = east(opponent_pawns >> 8) | west(opponent_pawns >> 8);
+
+        // Rear pawns of a file
+        let doubled = pawns & downfill(pawns >> 8);
+
+        // Pawns without friendly pawns on adjacent files
+        let files = filefill(pawns);
+        let isolated = pawns & !(east(files) | west(files));
+
+        // Pawns that cannot be protected by the advance of friendly pawns
+        // and that cannot advance safely
+        let stops = pawns << 8;
+        let backward = ((stops & opponent_attacks & !upfill(attacks)) >> 8) & !isolated;
+
+        // Pawns protected by a friendly pawn or next to one
+        let connected = pawns & (attacks | east(pawns) | west(pawns));
+
+        // Pawns without opponent pawns in front of them on the same and
+        // adjacent files
+        let front = downfill(opponent_pawns >> 8);
+        let passed = pawns & !(front | east(front) | west(front)) & !doubled;
+
+        let mut passed_score = [0; 2];
+        let mut bb = passed;
+        while let Some(square) = bb.next() {
+            let bonus = params.passed_pawn[square.rank() as usize];
+            passed_score[0] += bonus[0];
+            passed_score[1] += bonus[1];
+        }
+
+        let times = |n: Bitboard, y: [Score; 2]| {
+            let n = n.count() as Score;
+            [n * y[0], n * y[1]]
+        };
+
+        PawnStructure {
+            doubled: times(doubled, params.doubled_pawn),
+            isolated: times(isolated, params.isolated_pawn),
+            backward: times(backward, params.backward_pawn),
+            connected: times(connected, params.connected_pawn),
+            passed: passed_score,
+        }
+    }
+
+    fn total(&self) -> [Score; 2] {
+        let terms = [self.doubled, self.isolated, self.backward, self.connected, self.passed];
+        let mut score = [0; 2];
+        for term in &terms {
+            score[0] += term[0];
+            score[1] += term[1];
+        }
+        score
+    }
+}
+
+fn east(bb: Bitboard) -> Bitboard {
+    (bb << 1) & !FILE_A
+}
+
+fn west(bb: Bitboard) -> Bitboard {
+    (bb >> 1) & !FILE_H
+}
+
 #[allow(dead_code)]
 fn closed_files(white_pawns: Bitboard, black_pawns: Bitboard) -> Bitboard {
     filefill(white_pawns) & filefill(black_pawns)
 }
 
-#[allow(dead_code)]
 fn open_files(white_pawns: Bitboard, black_pawns: Bitboard) -> Bitboard {
     !filefill(white_pawns) & !filefill(black_pawns)
 }
 
-#[allow(dead_code)]
 fn half_open_files(pawns: Bitboard, opponent_pawns: Bitboard) -> Bitboard {
     !filefill(pawns) ^ open_files(pawns, opponent_pawns)
 }
@@ -295,6 +508,23 @@ mod tests {
         assert_eq!(game.eval(), 0);
     }
 
+    #[test]
+    fn test_phase() {
+        let game = Game::from_fen(DEFAULT_FEN).unwrap();
+        assert_eq!(game.phase(), MAX_PHASE);
+
+        // Many pawns but no pieces is an ending
+        let game = Game::from_fen("8/pp3kpp/2p5/8/8/2P5/PP3KPP/8 w - - 0 1").unwrap();
+        assert_eq!(game.phase(), 0);
+
+        let game = Game::from_fen("3qk3/pppppppp/8/8/8/8/PPPPPPPP/3QK3 w - - 0 1").unwrap();
+        assert_eq!(game.phase(), 8);
+
+        // Promotions cannot go beyond the opening
+        let game = Game::from_fen("QQQQk3/8/8/8/8/8/8/rnbqKbnr w - - 0 1").unwrap();
+        assert_eq!(game.phase(), MAX_PHASE);
+    }
+
     #[test]
     fn test_see() {
         let mut game = Game::new();
@@ -353,6 +583,70 @@ mod tests {
         assert_eq!(game.see(PieceMove::new(B3, B6, CAPTURE)), PAWN_VALUE - QUEEN_VALUE);
     }
 
+    #[test]
+    fn test_pawn_structure() {
+        let params = EvalParams::default();
+        let game = Game::from_fen("4k3/8/8/8/8/2P5/P1P1PP2/4K3 w - - 0 1").unwrap();
+        let white_pawns = game.bitboards[(WHITE | PAWN) as usize];
+        let black_pawns = game.bitboards[(BLACK | PAWN) as usize];
+        let pawns = PawnStructure::new(&params, white_pawns, black_pawns, WHITE);
+        let passed = params.passed_pawn[1];
+        assert_eq!(pawns.doubled, params.doubled_pawn);
+        assert_eq!(pawns.isolated, [3 * params.isolated_pawn[0], 3 * params.isolated_pawn[1]]);
+        assert_eq!(pawns.backward, [0, 0]);
+        assert_eq!(pawns.connected, [2 * params.connected_pawn[0], 2 * params.connected_pawn[1]]);
+        assert_eq!(pawns.passed, [3 * passed[0] + params.passed_pawn[2][0], 3 * passed[1] + params.passed_pawn[2][1]]);
+
+        let game = Game::from_fen("4k3/8/8/5p2/3P4/4P3/8/4K3 w - - 0 1").unwrap();
+        let white_pawns = game.bitboards[(WHITE | PAWN) as usize];
+        let black_pawns = game.bitboards[(BLACK | PAWN) as usize];
+        let pawns = PawnStructure::new(&params, white_pawns, black_pawns, WHITE);
+        assert_eq!(pawns.backward, params.backward_pawn);
+        assert_eq!(pawns.connected, params.connected_pawn);
+        assert_eq!(pawns.isolated, [0, 0]);
+
+        // The same structure seen from black
+        let pawns = PawnStructure::new(&params, white_pawns.swap_bytes(), black_pawns.swap_bytes(), BLACK);
+        assert_eq!(pawns.backward, params.backward_pawn);
+        assert_eq!(pawns.connected, params.connected_pawn);
+    }
+
+    #[test]
+    fn test_eval_pawns() {
+        let game = Game::from_fen("4k3/pp3p2/2p5/8/3P4/4P3/PP6/4K3 w - - 0 1").unwrap();
+        let score = game.eval_pawns();
+        assert_eq!(game.pawn_table.get(game.positions.top().pawn_hash), Some(score));
+        assert_eq!(game.eval_pawns(), score);
+
+        // Mirrored position
+        let game = Game::from_fen("4k3/pp6/4p3/3p4/8/2P5/PP3P2/4K3 b - - 0 1").unwrap();
+        assert_eq!(game.eval_pawns(), [-score[0], -score[1]]);
+    }
+
+    #[test]
+    fn test_king_safety() {
+        let params = EvalParams::default();
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let occupied = game.bitboard(WHITE) | game.bitboard(BLACK);
+        assert_eq!(game.eval_king_safety(WHITE, 0, 0, occupied), 0);
+        assert_eq!(game.eval_king_safety(BLACK, 0, 0, occupied), 0);
+
+        // A single attacker is not a threat
+        assert_eq!(game.eval_king_safety(WHITE, 1, 10, occupied), 0);
+        assert_eq!(game.eval_king_safety(WHITE, 2, 10, occupied), -10 * params.king_attackers_factors[2] / 100);
+
+        // Missing pawn in the shield on a half open file
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/5P1P/6K1 w - - 0 1").unwrap();
+        let occupied = game.bitboard(WHITE) | game.bitboard(BLACK);
+        assert_eq!(game.eval_king_safety(WHITE, 0, 0, occupied), params.pawn_shield[0] + params.half_open_king_file);
+
+        // Advanced pawn in the shield and pawn storm on a half open file
+        let game = Game::from_fen("6k1/5p1p/8/8/8/6Pp/5P2/6K1 w - - 0 1").unwrap();
+        let occupied = game.bitboard(WHITE) | game.bitboard(BLACK);
+        let malus = params.pawn_shield[2] + params.pawn_shield[0] + params.pawn_storm[2] + params.half_open_king_file;
+        assert_eq!(game.eval_king_safety(WHITE, 0, 0, occupied), malus);
+    }
+
     #[test]
     fn test_open_files() {
         let game = Game::from_fen("8/8/3k4/3p4/8/2PP4/3R1R2/3K4 w - - 0 1").unwrap();
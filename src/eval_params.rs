@@ -0,0 +1,215 @@
+use std::prelude::v1::*;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::common::*;
+use crate::piece::*;
+use crate::square::*;
+use crate::eval::{PAWN_VALUE, KNIGHT_VALUE, BISHOP_VALUE, ROOK_VALUE, QUEEN_VALUE};
+use crate::piece_square_table::{WHITE_PIECES_OPENING, WHITE_PIECES_ENDING};
+
+// Index in `PIECES` of each piece kind >> 1
+const PIECES_INDEXES: [usize; 7] = [0, 0, 1, 5, 2, 3, 4];
+
+/// Weights of the evaluation terms
+///
+/// The scores given in pairs are the values of a term in the opening and
+/// ending phases, and the tables indexed by piece kinds follow the order of
+/// `PIECES` without the kings when they don't apply to them.
+///
+/// The parameters can be saved and loaded in a simple text format made of
+/// the name of each parameter followed by its values, where missing
+/// parameters keep their default values and comments start with `#`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    pub material: [[Score; 2]; 5], // By piece kind
+    pub bishop_pair: [Score; 2],
+    pub mobility: [Score; 2], // By attacked square
+    pub doubled_pawn: [Score; 2],
+    pub isolated_pawn: [Score; 2],
+    pub backward_pawn: [Score; 2],
+    pub connected_pawn: [Score; 2],
+    pub passed_pawn: [[Score; 2]; 8], // By rank
+    pub king_attack_weights: [Score; 4], // By piece kind, from knight to queen
+    pub king_attackers_factors: [Score; 8], // In percent, by number of attackers
+    pub weak_king_square: Score,
+    pub open_king_file: Score,
+    pub half_open_king_file: Score,
+    pub pawn_shield: [Score; 4], // By distance to the king, 0 for no pawn
+    pub pawn_storm: [Score; 4], // By distance to the king, 0 for no pawn
+    pub pst_opening: [[Score; 64]; 6], // By piece kind, from rank 8 to 1 for white
+    pub pst_ending: [[Score; 64]; 6],
+}
+
+impl EvalParams {
+    /// Get the piece square table scores of the given piece on the given
+    /// square in opening and ending phases
+    #[inline]
+    pub fn pst(&self, piece: Piece, square: Square) -> [Score; 2] {
+        let i = PIECES_INDEXES[(piece.kind() >> 1) as usize];
+        let s = square.flip(piece.color() ^ 1) as usize;
+        [self.pst_opening[i][s], self.pst_ending[i][s]]
+    }
+
+    /// Get the name, the number of values per line, and the values of every
+    /// parameter
+    pub fn params_mut(&mut self) -> Vec<(&'static str, usize, &mut [Score])> {
+        let [pawn_pst_opening, knight_pst_opening, bishop_pst_opening,
+             rook_pst_opening, queen_pst_opening, king_pst_opening] = &mut self.pst_opening;
+        let [pawn_pst_ending, knight_pst_ending, bishop_pst_ending,
+             rook_pst_ending, queen_pst_ending, king_pst_ending] = &mut self.pst_ending;
+
+        vec![
+            ("material",               2, self.material.as_flattened_mut()),
+            ("bishop_pair",            2, &mut self.bishop_pair),
+            ("mobility",               2, &mut self.mobility),
+            ("doubled_pawn",           2, &mut self.doubled_pawn),
+            ("isolated_pawn",          2, &mut self.isolated_pawn),
+            ("backward_pawn",          2, &mut self.backward_pawn),
+            ("connected_pawn",         2, &mut self.connected_pawn),
+            ("passed_pawn",            2, self.passed_pawn.as_flattened_mut()),
+            ("king_attack_weights",    4, &mut self.king_attack_weights),
+            ("king_attackers_factors", 8, &mut self.king_attackers_factors),
+            ("weak_king_square",       1, std::slice::from_mut(&mut self.weak_king_square)),
+            ("open_king_file",         1, std::slice::from_mut(&mut self.open_king_file)),
+            ("half_open_king_file",    1, std::slice::from_mut(&mut self.half_open_king_file)),
+            ("pawn_shield",            4, &mut self.pawn_shield),
+            ("pawn_storm",             4, &mut self.pawn_storm),
+            ("pawn_pst_opening",       8, pawn_pst_opening),
+            ("knight_pst_opening",     8, knight_pst_opening),
+            ("bishop_pst_opening",     8, bishop_pst_opening),
+            ("rook_pst_opening",       8, rook_pst_opening),
+            ("queen_pst_opening",      8, queen_pst_opening),
+            ("king_pst_opening",       8, king_pst_opening),
+            ("pawn_pst_ending",        8, pawn_pst_ending),
+            ("knight_pst_ending",      8, knight_pst_ending),
+            ("bishop_pst_ending",      8, bishop_pst_ending),
+            ("rook_pst_ending",        8, rook_pst_ending),
+            ("queen_pst_ending",       8, queen_pst_ending),
+            ("king_pst_ending",        8, king_pst_ending),
+        ]
+    }
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams {
+            material: [
+                [PAWN_VALUE, 120],
+                [KNIGHT_VALUE, 330],
+                [BISHOP_VALUE, 350],
+                [ROOK_VALUE, 530],
+                [QUEEN_VALUE, 1000],
+            ],
+            bishop_pair: [40, 60],
+            mobility: [1, 1],
+            doubled_pawn: [-10, -20],
+            isolated_pawn: [-10, -15],
+            backward_pawn: [-8, -10],
+            connected_pawn: [5, 5],
+            passed_pawn: [
+                [0, 0], [5, 10], [5, 15], [10, 25], [20, 45], [35, 75], [60, 120], [0, 0]
+            ],
+            king_attack_weights: [2, 2, 3, 5],
+            king_attackers_factors: [0, 0, 50, 75, 88, 94, 97, 99],
+            weak_king_square: -5,
+            open_king_file: -20,
+            half_open_king_file: -10,
+            pawn_shield: [-25, 0, -10, -20],
+            pawn_storm: [0, -5, -20, -10],
+            pst_opening: WHITE_PIECES_OPENING,
+            pst_ending: WHITE_PIECES_ENDING,
+        }
+    }
+}
+
+impl FromStr for EvalParams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = EvalParams::default();
+
+        // Group the values of each parameter after its name
+        let mut groups: Vec<(&str, Vec<Score>)> = Vec::new();
+        for line in s.lines() {
+            let line = line.split('#').next().unwrap();
+            for token in line.split_whitespace() {
+                if let Ok(value) = token.parse::<Score>() {
+                    match groups.last_mut() {
+                        Some((_, values)) => values.push(value),
+                        None => return Err("missing parameter name".into()),
+                    }
+                } else {
+                    groups.push((token, Vec::new()));
+                }
+            }
+        }
+
+        let mut list = params.params_mut();
+        for (name, values) in groups {
+            match list.iter_mut().find(|(n, _, _)| *n == name) {
+                Some((_, _, param)) => {
+                    if param.len() != values.len() {
+                        return Err(format!("expected {} values for '{}'", param.len(), name));
+                    }
+                    param.copy_from_slice(&values);
+                },
+                None => {
+                    return Err(format!("unknown parameter '{}'", name));
+                }
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut params = self.clone();
+        for (name, width, values) in params.params_mut() {
+            writeln!(f, "{}", name)?;
+            for row in values.chunks(width) {
+                let row: Vec<String> = row.iter().map(|v| format!("{:>5}", v)).collect();
+                writeln!(f, "{}", row.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::*;
+
+    #[test]
+    fn test_pst() {
+        let params = EvalParams::default();
+        let white_score = params.pst(WHITE_KNIGHT, F3);
+        let black_score = params.pst(BLACK_KNIGHT, F3.flip(BLACK));
+        assert!(white_score[0] > 0);
+        assert_eq!(white_score, black_score);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut params = EvalParams::default();
+        params.bishop_pair = [45, 55];
+        params.pst_ending[1][27] = -7;
+
+        let s = params.to_string();
+        assert_eq!(s.parse::<EvalParams>(), Ok(params));
+
+        // Missing parameters keep their default values
+        let s = "# Comment\nmobility 2 3\nweak_king_square -8 # Comment\n";
+        let params = s.parse::<EvalParams>().unwrap();
+        assert_eq!(params.mobility, [2, 3]);
+        assert_eq!(params.weak_king_square, -8);
+        assert_eq!(params.material, EvalParams::default().material);
+
+        assert!("mobility 2".parse::<EvalParams>().is_err());
+        assert!("speed 2 3".parse::<EvalParams>().is_err());
+        assert!("2 3".parse::<EvalParams>().is_err());
+    }
+}
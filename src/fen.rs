@@ -56,6 +56,9 @@ impl FEN for Game {
                         self.bitboards[(p) as usize].set(sq);
                         self.bitboards[(p & 1) as usize].set(sq); // TODO: p.color()
                         position.hash ^= self.zobrist.pieces[p as usize][sq as usize];
+                        if p.kind() == PAWN {
+                            position.pawn_hash ^= self.zobrist.pieces[p as usize][sq as usize];
+                        }
                         1
                     },
                     _ => {
@@ -1,5 +1,6 @@
 use std::prelude::v1::*;
 use std::fmt;
+use std::sync::Arc;
 
 use crate::board;
 use crate::color::*;
@@ -7,60 +8,89 @@ use crate::piece::*;
 use crate::common::*;
 use crate::bitboard::Bitboard;
 use crate::clock::Clock;
+use crate::eval_params::EvalParams;
 use crate::piece_move::PieceMove;
 use crate::piece_move_list::PieceMoveList;
+use crate::pawn_hash_table::PawnHashTable;
+use crate::perft_table::PerftTable;
 use crate::positions::Positions;
+use crate::pv_table::PvTable;
+use crate::search::{PruningMargins, SearchExtensions, SearchStats};
+use crate::search_handle::SearchHandle;
+use crate::search_observer::SearchObserver;
+use crate::skill::Skill;
+use crate::tablebase::Tablebases;
 use crate::transposition_table::TranspositionTable;
 use crate::zobrist::Zobrist;
 use crate::piece::{PieceAttr, PieceChar};
-#[cfg(feature = "std")]
-use crate::protocols::Protocol;
 
 /// A `Game` type to store the state of a chess game
 #[derive(Clone)]
 pub struct Game {
-    #[cfg(feature = "std")]
-    pub protocol: Protocol,
-
     pub starting_fen: String,
     pub is_debug: bool,  // Print debugging
     pub is_eval_verbose: bool, // Print thinking in eval
     pub is_search_verbose: bool, // Print thinking in search
     pub show_coordinates: bool,
     pub threads_count: usize,
+    pub multipv: usize, // Number of best lines to search
+    pub extensions: SearchExtensions,
+    pub margins: PruningMargins,
+    pub skill: Skill, // Limit the strength of the engine
+    pub contempt: Score, // Score of a draw for the opponent of the root side
+    pub eval_params: EvalParams, // Weights of the evaluation terms
+    pub search_stats: SearchStats,
+    pub observer: Option<Arc<dyn SearchObserver>>, // Receive search events
     pub nodes_count: u64,
     pub clock: Clock,
+    pub(crate) search_handle: SearchHandle,
+    pub(crate) is_helper: bool, // Helper thread of a parallel search
     pub bitboards: [Bitboard; 14],
     pub board: [Piece; 64],
     pub moves: PieceMoveList,
+    pub pv_table: PvTable,
     pub positions: Positions,
     pub zobrist: Zobrist,
     pub history: Vec<PieceMove>,
-    pub tt: TranspositionTable
+    pub tt: TranspositionTable,
+    pub pawn_table: PawnHashTable, // Cache of the pawn structure scores
+    pub(crate) perft_table: Option<Arc<PerftTable>>, // Allocated by the first perft
+    pub tablebases: Tablebases
 }
 
 impl Game {
     /// Create a new `Game`
     pub fn new() -> Game {
         Game {
-            #[cfg(feature = "std")]
-            protocol: Protocol::CLI,
-
             starting_fen: String::from(DEFAULT_FEN),
             is_debug: false,
             is_eval_verbose: false,
             is_search_verbose: false,
             show_coordinates: false,
             threads_count: 0,
+            multipv: 1,
+            extensions: SearchExtensions::default(),
+            margins: PruningMargins::default(),
+            skill: Skill::default(),
+            contempt: 0,
+            eval_params: EvalParams::default(),
+            search_stats: SearchStats::default(),
+            observer: None,
             nodes_count: 0,
             clock: Clock::new(40, 5 * 60),
+            search_handle: SearchHandle::new(),
+            is_helper: false,
             bitboards: [0; 14],
             board: [EMPTY; 64],
             moves: PieceMoveList::new(),
+            pv_table: PvTable::new(),
             positions: Positions::new(),
             zobrist: Zobrist::new(),
             history: Vec::new(),
-            tt: TranspositionTable::with_memory(TT_SIZE)
+            tt: TranspositionTable::with_memory(TT_SIZE),
+            pawn_table: PawnHashTable::with_memory(PAWN_HASH_SIZE),
+            perft_table: None,
+            tablebases: Tablebases::new()
         }
     }
 
@@ -75,11 +105,18 @@ impl Game {
         self.tt = TranspositionTable::with_memory(memory);
     }
 
+    /// Set the weights of the evaluation terms
+    pub fn set_eval_params(&mut self, params: EvalParams) {
+        self.eval_params = params;
+        self.pawn_table.clear(); // The cached scores are no longer valid
+    }
+
     /// Clear the current game state
     pub fn clear(&mut self) {
         self.bitboards = [0; 14];
         self.board = [EMPTY; 64];
         self.moves.clear_all();
+        self.moves.clear_history();
         self.positions.clear();
         self.history.clear();
         self.tt.clear();
@@ -11,9 +11,13 @@
 //!
 //! let mut game = Game::from_fen(fen).unwrap();
 //!
-//! game.clock = Clock::new(1, 5000); // Search 1 move in 5 seconds
+//! let limits = SearchLimits {
+//!     depth: Some(14), // Search from depth 1 to 14
+//!     clock: Some(Clock::new(1, 5000)), // Search 1 move in 5 seconds
+//!     ..SearchLimits::new()
+//! };
 //!
-//! match game.search(1..15) { // Search from depth 1 to 15
+//! match game.search(limits) {
 //!     Some(m) => {
 //!         assert_eq!(game.move_to_san(m), "Bxc4");
 //!
@@ -53,9 +57,12 @@ mod board;
 mod common;
 mod dumb7fill;
 mod hyperbola;
+mod pawn_hash_table;
+mod perft_table;
 mod piece_move;
 mod piece_move_list;
 mod positions;
+mod pv_table;
 mod piece_square_table;
 mod transposition;
 mod transposition_table;
@@ -73,6 +80,9 @@ pub mod color;
 /// Evaluation algorithms
 pub mod eval;
 
+/// Evaluation parameters
+pub mod eval_params;
+
 /// Forsyth–Edwards Notation support
 pub mod fen;
 
@@ -98,9 +108,28 @@ pub mod protocols;
 /// Search algorithms
 pub mod search;
 
+/// Search handle
+pub mod search_handle;
+
+/// Search limits
+pub mod search_limits;
+
+/// Search observer
+pub mod search_observer;
+
+/// Skill level
+pub mod skill;
+
 /// Square type
 pub mod square;
 
+/// Endgame tablebases
+pub mod tablebase;
+
+/// Tuning of evaluation parameters
+#[cfg(feature = "std")]
+pub mod tuner;
+
 /// Chess prelude
 pub mod chess {
     pub use crate::attack::Attack;
@@ -111,6 +140,8 @@ pub mod chess {
     pub use crate::piece_move_generator::PieceMoveGenerator;
     pub use crate::piece_move_notation::PieceMoveNotation;
     pub use crate::search::Search;
+    pub use crate::search_limits::SearchLimits;
+    pub use crate::search_observer::SearchObserver;
 }
 
 use std::prelude::v1::*;
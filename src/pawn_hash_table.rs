@@ -0,0 +1,78 @@
+use std::prelude::v1::*;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::common::*;
+
+// The key of an entry is xored with its data to detect entries modified by
+// another thread between the reads of the two words.
+#[derive(Default)]
+struct PawnEntry {
+    key: AtomicU64,
+    data: AtomicU64, // Opening score in bits 16 to 31 and ending score in bits 0 to 15
+}
+
+/// Hash table of the pawn structure scores indexed by the pawn hash of the
+/// positions, shared between the threads of a search without locks
+#[derive(Clone)]
+pub struct PawnHashTable {
+    entries: Arc<[PawnEntry]>,
+}
+
+impl PawnHashTable {
+    pub fn with_memory(memory: usize) -> PawnHashTable {
+        let n = memory / mem::size_of::<PawnEntry>();
+        let n = if n.is_power_of_two() { n } else { n.next_power_of_two() / 2 };
+        let mut entries = Vec::with_capacity(n);
+        entries.resize_with(n, PawnEntry::default);
+        PawnHashTable { entries: entries.into() }
+    }
+
+    /// Get the opening and ending scores of the pawn structure
+    pub fn get(&self, hash: u64) -> Option<[Score; 2]> {
+        let entry = &self.entries[(hash & (self.entries.len() as u64 - 1)) as usize];
+        let data = entry.data.load(Ordering::Relaxed);
+        let key = entry.key.load(Ordering::Relaxed);
+        if key ^ data == hash {
+            Some([(data >> 16) as Score, data as Score])
+        } else {
+            None
+        }
+    }
+
+    /// Set the opening and ending scores of the pawn structure
+    pub fn set(&self, hash: u64, score: [Score; 2]) {
+        let entry = &self.entries[(hash & (self.entries.len() as u64 - 1)) as usize];
+        let data = ((score[0] as u16 as u64) << 16) | (score[1] as u16 as u64);
+        entry.key.store(hash ^ data, Ordering::Relaxed);
+        entry.data.store(data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for entry in self.entries.iter() {
+            entry.key.store(0, Ordering::Relaxed);
+            entry.data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pawn_hash_table() {
+        let table = PawnHashTable::with_memory(1 << 10);
+        assert_eq!(table.entries.len(), 64);
+
+        let hash = 0x1234_5678_9ABC_DEF0;
+        assert_eq!(table.get(hash), None);
+        table.set(hash, [-25, 130]);
+        assert_eq!(table.get(hash), Some([-25, 130]));
+        assert_eq!(table.get(hash ^ 1), None);
+
+        table.clear();
+        assert_eq!(table.get(hash), None);
+    }
+}
@@ -0,0 +1,65 @@
+use std::prelude::v1::*;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::common::*;
+
+// The key of an entry is xored with its data to detect entries modified by
+// another thread between the reads of the two words.
+#[derive(Default)]
+struct PerftEntry {
+    key: AtomicU64,
+    data: AtomicU64, // Nodes count in the upper 56 bits and depth in the lower 8
+}
+
+/// Hash table of the nodes counts of perft sub-trees that can be shared
+/// between threads without locks
+pub struct PerftTable {
+    entries: Box<[PerftEntry]>,
+}
+
+impl PerftTable {
+    pub fn with_memory(memory: usize) -> PerftTable {
+        let n = memory / mem::size_of::<PerftEntry>();
+        let n = if n.is_power_of_two() { n } else { n.next_power_of_two() / 2 };
+        let mut entries = Vec::with_capacity(n);
+        entries.resize_with(n, PerftEntry::default);
+        PerftTable { entries: entries.into_boxed_slice() }
+    }
+
+    pub fn get(&self, hash: u64, depth: Depth) -> Option<u64> {
+        let entry = &self.entries[(hash & (self.entries.len() as u64 - 1)) as usize];
+        let data = entry.data.load(Ordering::Relaxed);
+        let key = entry.key.load(Ordering::Relaxed);
+        if key ^ data == hash && data & 0xFF == depth as u64 {
+            Some(data >> 8)
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&self, hash: u64, depth: Depth, count: u64) {
+        let entry = &self.entries[(hash & (self.entries.len() as u64 - 1)) as usize];
+        let data = (count << 8) | depth as u64;
+        entry.key.store(hash ^ data, Ordering::Relaxed);
+        entry.data.store(data, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perft_table() {
+        let table = PerftTable::with_memory(1 << 10);
+        assert_eq!(table.entries.len(), 64);
+
+        let hash = 0x1234_5678_9ABC_DEF0;
+        assert_eq!(table.get(hash, 3), None);
+        table.set(hash, 3, 8902);
+        assert_eq!(table.get(hash, 3), Some(8902));
+        assert_eq!(table.get(hash, 2), None);
+        assert_eq!(table.get(hash ^ 1, 3), None);
+    }
+}
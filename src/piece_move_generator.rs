@@ -57,6 +57,7 @@ pub trait PieceMoveGenerator {
 }
 
 trait PieceMoveGeneratorExt {
+    fn sort_quiet_moves(&mut self);
     fn is_move_legal(&mut self, m: PieceMove) -> bool;
     fn mvv_lva(&self, m: PieceMove) -> u8;
     fn can_king_castle(&mut self, side: Color) -> bool;
@@ -100,6 +101,9 @@ impl PieceMoveGenerator for Game {
                     if self.can_queen_castle(side) {
                         self.moves.add_queen_castle(side);
                     }
+                    if !self.moves.skip_ordering {
+                        self.sort_moves();
+                    }
                 }
             },
             _ => () // Nothing to do in `BestPieceMove` or `Done` stages
@@ -107,6 +111,10 @@ impl PieceMoveGenerator for Game {
     }
 
     fn sort_moves(&mut self) {
+        if self.moves.stage() == PieceMoveListStage::QuietPieceMove {
+            return self.sort_quiet_moves();
+        }
+
         // Sort all moves currently in the list except the best move
         let a = if self.moves[0].score == BEST_MOVE_SCORE { 1 } else { 0 };
         let b = self.moves.len();
@@ -179,6 +187,7 @@ impl PieceMoveGenerator for Game {
 
             if piece.kind() == PAWN {
                 position.halfmoves_count = 0;
+                position.pawn_hash ^= self.zobrist.pieces[piece as usize][m.from() as usize];
             }
 
             if piece.kind() == KING || (piece.kind() == ROOK && m.from() == H1.flip(side)) {
@@ -199,6 +208,9 @@ impl PieceMoveGenerator for Game {
             self.board[m.to() as usize] = p;
             self.bitboards[p as usize].toggle(m.to());
             position.hash ^= self.zobrist.pieces[p as usize][m.to() as usize];
+            if p.kind() == PAWN {
+                position.pawn_hash ^= self.zobrist.pieces[p as usize][m.to() as usize];
+            }
 
             if m.is_en_passant() {
                 let sq = (((m.to().flip(side) as Shift) + DOWN) as Square).flip(side);
@@ -207,11 +219,15 @@ impl PieceMoveGenerator for Game {
                 self.bitboards[pawn as usize].toggle(sq);
                 self.bitboards[(side ^ 1) as usize].toggle(sq);
                 position.hash ^= self.zobrist.pieces[pawn as usize][sq as usize];
+                position.pawn_hash ^= self.zobrist.pieces[pawn as usize][sq as usize];
             } else if capture != EMPTY {
                 position.halfmoves_count = 0;
                 self.bitboards[capture as usize].toggle(m.to());
                 self.bitboards[(side ^ 1) as usize].toggle(m.to());
                 position.hash ^= self.zobrist.pieces[capture as usize][m.to() as usize];
+                if capture.kind() == PAWN {
+                    position.pawn_hash ^= self.zobrist.pieces[capture as usize][m.to() as usize];
+                }
 
                 // Update opponent's castling rights on rook capture
                 if capture.kind() == ROOK {
@@ -265,7 +281,7 @@ impl PieceMoveGenerator for Game {
         position.hash ^= self.zobrist.side;
 
         self.positions.push(position);
-        self.moves.inc();
+        self.moves.inc(m);
     }
 
     fn undo_move(&mut self, m: PieceMove) {
@@ -320,6 +336,34 @@ impl PieceMoveGenerator for Game {
 }
 
 impl PieceMoveGeneratorExt for Game {
+    fn sort_quiet_moves(&mut self) {
+        // Sort the quiet moves that were just generated, with the countermove
+        // first and the others by history
+        let a = self.moves.index();
+        let b = self.moves.len();
+        let side = self.side();
+        let countermove = self.moves.get_countermove();
+        let mut keys = [0; MAX_MOVES];
+        for (i, key) in keys.iter_mut().enumerate().take(b).skip(a) {
+            let m = self.moves[i].item;
+            *key = if m == countermove {
+                u32::MAX
+            } else {
+                self.moves.get_history(side, m)
+            };
+        }
+
+        // Stable insertion sort
+        for i in (a + 1)..b {
+            let mut j = i;
+            while j > a && keys[j - 1] < keys[j] {
+                keys.swap(j - 1, j);
+                self.moves.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+    }
+
     fn can_castle_on(&mut self, side: Color, wing: Piece) -> bool {
         match wing {
             QUEEN => self.can_queen_castle(side),
@@ -557,6 +601,7 @@ mod tests {
             let copy = Game::from_fen(&fen).unwrap();
             assert_eq!(copy.to_fen().as_str(), fen);
             assert_eq!(copy.positions.top().hash, game.positions.top().hash);
+            assert_eq!(copy.positions.top().pawn_hash, game.positions.top().pawn_hash);
         }
 
         for m in moves.iter().rev() {
@@ -565,6 +610,7 @@ mod tests {
             let copy = Game::from_fen(&fen).unwrap();
             assert_eq!(copy.to_fen().as_str(), fen);
             assert_eq!(copy.positions.top().hash, game.positions.top().hash);
+            assert_eq!(copy.positions.top().pawn_hash, game.positions.top().pawn_hash);
         }
 
         assert_eq!(game.to_fen().as_str(), DEFAULT_FEN);
@@ -796,6 +842,39 @@ mod tests {
         assert_eq!(n, 31);
     }
 
+    #[test]
+    fn test_moves_order_with_history_and_countermove() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let mut game = Game::from_fen(fen).unwrap();
+
+        // Reach the position with the previous move known
+        let m = game.move_from_lan("d7d5");
+        game.make_move(m);
+
+        let capture = game.move_from_lan("e4d5");
+        let countermove = game.move_from_lan("b1c3");
+        let history_move_1 = game.move_from_lan("g1f3");
+        let history_move_2 = game.move_from_lan("d2d4");
+        game.moves.add_countermove(countermove);
+        game.moves.add_history(WHITE, history_move_1, 4);
+        game.moves.add_history(WHITE, history_move_2, 2);
+
+        game.moves.clear();
+
+        let mut n = 0;
+        while let Some(m) = game.next_move() {
+            match n {
+                0 => assert_eq!(m, capture),
+                1 => assert_eq!(m, countermove),
+                2 => assert_eq!(m, history_move_1),
+                3 => assert_eq!(m, history_move_2),
+                _ => {}
+            }
+            n += 1;
+        }
+        assert_eq!(n, 31);
+    }
+
     #[test]
     fn test_moves_order_when_best_move_is_quiet_move() {
         // Ruy Lopez Opening: Morphy Defense (1. e4 e5 2. Nf3 Nc6 3. Bb5 a6)
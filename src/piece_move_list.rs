@@ -44,16 +44,31 @@ impl From<PieceMoveListStage> for PieceMoveType {
     }
 }
 
+// Maximum value of a history entry before aging the table
+const MAX_HISTORY: u32 = 1 << 24;
+
 #[derive(Clone)]
 pub struct PieceMoveList {
     killers: [[PieceMove; MAX_KILLERS]; MAX_PLY],
 
+    // Butterfly history of quiet moves causing a beta cutoff, indexed by
+    // side, from square, and to square.
+    history: Box<[[[u32; 64]; 64]; 2]>, // Kept on the heap to keep games small
+
+    // Quiet moves causing a beta cutoff in reply to the previous move,
+    // indexed by its from and to squares.
+    countermoves: Box<[[PieceMove; 64]; 64]>,
+
+    // Move played to reach a given ply.
+    prev_moves: [PieceMove; MAX_PLY],
+
     // We store the generated moves for each ply in a two dimensional array
     // used by the recursive search function. It must be able to store any
     // ply up to `MAX_PLY`, the theoretical maximum number of plies in a chess
     // game. And likewise it must be able to store the generated moves up to
-    // the maximum of any chess position `MAX_MOVES`.
-    lists: [[Scored<PieceMove, u8>; MAX_MOVES]; MAX_PLY],
+    // the maximum of any chess position `MAX_MOVES`. It is kept on the heap
+    // to keep games small.
+    lists: Vec<[Scored<PieceMove, u8>; MAX_MOVES]>,
 
     // Number of moves at a given ply.
     sizes: [usize; MAX_PLY],
@@ -74,7 +89,10 @@ impl PieceMoveList {
     pub fn new() -> PieceMoveList {
         PieceMoveList {
             killers: [[PieceMove::new_null(); MAX_KILLERS]; MAX_PLY],
-            lists: [[Scored::new(PieceMove::new_null(), 0); MAX_MOVES]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            countermoves: Box::new([[PieceMove::new_null(); 64]; 64]),
+            prev_moves: [PieceMove::new_null(); MAX_PLY],
+            lists: vec![[Scored::new(PieceMove::new_null(), 0); MAX_MOVES]; MAX_PLY],
             sizes: [0; MAX_PLY],
             indexes: [0; MAX_PLY],
             stages: [PieceMoveListStage::BestPieceMove; MAX_PLY],
@@ -84,8 +102,14 @@ impl PieceMoveList {
         }
     }
 
-    pub fn inc(&mut self) {
+    pub fn inc(&mut self, m: PieceMove) {
         self.ply += 1;
+
+        // NOTE: the ply counter is only reset at the beginning of a search
+        // so it could go past `MAX_PLY` when moves are played outside of it.
+        if self.ply < MAX_PLY {
+            self.prev_moves[self.ply] = m;
+        }
     }
 
     pub fn dec(&mut self) {
@@ -105,6 +129,9 @@ impl PieceMoveList {
 
     pub fn clear_all(&mut self) {
         self.killers = [[PieceMove::new_null(); MAX_KILLERS]; MAX_PLY];
+        *self.countermoves = [[PieceMove::new_null(); 64]; 64];
+        self.prev_moves = [PieceMove::new_null(); MAX_PLY];
+        self.age_history();
         self.sizes = [0; MAX_PLY];
         self.indexes = [0; MAX_PLY];
         self.stages = [PieceMoveListStage::BestPieceMove; MAX_PLY];
@@ -359,6 +386,57 @@ impl PieceMoveList {
             self.killers[self.ply][0] = killer_move;
         }
     }
+
+    pub fn get_history(&self, side: Color, m: PieceMove) -> u32 {
+        self.history[side as usize][m.from() as usize][m.to() as usize]
+    }
+
+    pub fn add_history(&mut self, side: Color, m: PieceMove, depth: Depth) {
+        let bonus = (depth as u32) * (depth as u32);
+        let entry = &mut self.history[side as usize][m.from() as usize][m.to() as usize];
+        *entry += bonus;
+        if *entry > MAX_HISTORY {
+            self.age_history();
+        }
+    }
+
+    /// Divide the history table by two to favor recent cutoffs
+    pub fn age_history(&mut self) {
+        for entry in self.history.iter_mut().flatten().flatten() {
+            *entry /= 2;
+        }
+    }
+
+    pub fn clear_history(&mut self) {
+        *self.history = [[[0; 64]; 64]; 2];
+        *self.countermoves = [[PieceMove::new_null(); 64]; 64];
+    }
+
+    /// Get the current ply counted from the beginning of the search
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    /// Get the move played to reach the current ply
+    pub fn prev_move(&self) -> PieceMove {
+        self.prev_moves[self.ply]
+    }
+
+    /// Get the move that caused a cutoff in reply to the previous move
+    pub fn get_countermove(&self) -> PieceMove {
+        let prev = self.prev_moves[self.ply];
+        if prev.is_null() {
+            return prev;
+        }
+        self.countermoves[prev.from() as usize][prev.to() as usize]
+    }
+
+    pub fn add_countermove(&mut self, m: PieceMove) {
+        let prev = self.prev_moves[self.ply];
+        if !prev.is_null() {
+            self.countermoves[prev.from() as usize][prev.to() as usize] = m;
+        }
+    }
 }
 
 impl Iterator for PieceMoveList {
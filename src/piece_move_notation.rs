@@ -281,7 +281,7 @@ mod tests {
         let fen = "7k/3P1ppp/4PQ2/8/8/8/8/6RK w - - 0 1";
         let mut game = Game::from_fen(fen).unwrap();
 
-        // NOTE: This move should end with `#` but this is added in `search::get_pv()`.
+        // NOTE: This move should end with `#` but this is added in `search_observer::pv_to_san()`.
         assert_eq!(game.move_to_san(PieceMove::new(F6, G7, CAPTURE)), "Qxg7");
 
         let fen = "1q3rk1/Pbpp1p1p/2nb1n1Q/1p2p1pP/2NPP3/1B3N2/1PPB1PP1/R3K2R w KQ g6 0 25";
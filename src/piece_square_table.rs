@@ -1,7 +1,4 @@
 use crate::common::*;
-use crate::color::*;
-use crate::piece::*;
-use crate::square::*;
 
 const WHITE_PAWN_OPENING: [Score; 64] = [
     0,   0,   0,   0,   0,   0,   0,   0,
@@ -135,7 +132,7 @@ const WHITE_KING_ENDING: [Score; 64] = [
   -50, -30, -30, -30, -30, -30, -30, -50
 ];
 
-const WHITE_PIECES_OPENING: [[Score; 64]; 6] = [
+pub const WHITE_PIECES_OPENING: [[Score; 64]; 6] = [
     WHITE_PAWN_OPENING,
     WHITE_KNIGHT_OPENING,
     WHITE_BISHOP_OPENING,
@@ -144,7 +141,7 @@ const WHITE_PIECES_OPENING: [[Score; 64]; 6] = [
     WHITE_KING_OPENING
 ];
 
-const WHITE_PIECES_ENDING: [[Score; 64]; 6] = [
+pub const WHITE_PIECES_ENDING: [[Score; 64]; 6] = [
     WHITE_PAWN_ENDING,
     WHITE_KNIGHT_ENDING,
     WHITE_BISHOP_ENDING,
@@ -152,39 +149,3 @@ const WHITE_PIECES_ENDING: [[Score; 64]; 6] = [
     WHITE_QUEEN_ENDING,
     WHITE_KING_ENDING
 ];
-
-lazy_static! {
-    pub static ref PST: [[[Score; 2]; 64]; 14] = {
-        let mut pst_values = [[[0; 2]; 64]; 14];
-
-        for c in 0..2 {
-            for p in 0..6 {
-                for s in 0..64 {
-                    let square = (s as Square).flip((c as Color) ^ 1);
-                    let piece = (c as Color) | PIECES[p];
-
-                    let score = WHITE_PIECES_OPENING[p][s];
-                    pst_values[piece as usize][square as usize][0] = score;
-
-                    let score = WHITE_PIECES_ENDING[p][s];
-                    pst_values[piece as usize][square as usize][1] = score;
-                }
-            }
-        }
-
-        pst_values
-    };
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_pst() {
-        let white_score = PST[WHITE_KNIGHT as usize][F3.flip(WHITE) as usize][0];
-        let black_score = PST[BLACK_KNIGHT as usize][F3.flip(BLACK) as usize][0];
-        assert!(white_score > 0);
-        assert_eq!(white_score, black_score);
-    }
-}
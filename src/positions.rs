@@ -8,6 +8,7 @@ use crate::square::*;
 #[derive(Copy, Clone)]
 pub struct Position {
     pub hash: u64,
+    pub pawn_hash: u64, // Hash of the pawns only
     pub side: Color,
     pub capture: Piece, // TODO: use `Option<Piece>`?
     pub en_passant: Square, // TODO: use `Option<Square>`?
@@ -28,6 +29,7 @@ impl Position {
     pub fn new() -> Position {
         Position {
             hash: 0, // TODO: is it a problem for the starting position?
+            pawn_hash: 0,
             side: WHITE,
             capture: EMPTY, // TODO: use `None`?
             en_passant: OUT, // TODO: use `None`?
@@ -168,14 +170,14 @@ mod tests {
 
     #[test]
     fn test_size_of_position() {
-        assert_eq!(mem::size_of::<u64>(),       8); // x1
+        assert_eq!(mem::size_of::<u64>(),       8); // x2
         assert_eq!(mem::size_of::<u8>(),        1); // x2
         assert_eq!(mem::size_of::<bool>(),      1); // x1
         assert_eq!(mem::size_of::<Color>(),     1); // x1
         assert_eq!(mem::size_of::<Piece>(),     1); // x1
         assert_eq!(mem::size_of::<Square>(),    1); // x1
 
-        assert_eq!(mem::size_of::<Position>(), 16);
+        assert_eq!(mem::size_of::<Position>(), 24);
     }
 
     #[test]
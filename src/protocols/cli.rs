@@ -10,6 +10,7 @@ use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::error::Error;
+use std::sync::Arc;
 
 use crate::version;
 use crate::color::*;
@@ -17,6 +18,7 @@ use crate::common::*;
 use crate::attack::Attack;
 use crate::clock::Clock;
 use crate::eval::Eval;
+use crate::eval_params::EvalParams;
 use crate::fen::FEN;
 use crate::game::Game;
 use crate::piece_move_generator::PieceMoveGenerator;
@@ -24,12 +26,18 @@ use crate::piece_move_notation::PieceMoveNotation;
 use crate::pgn::*;
 use crate::protocols::xboard::XBoard;
 use crate::protocols::uci::UCI;
-use crate::search::Search;
+use crate::protocols::{print_thinking_header, thinking_pv};
+use crate::search::{Search, mate_in};
+use crate::search_limits::SearchLimits;
+use crate::search_observer::{SearchInfo, SearchObserver};
+use crate::skill::{Skill, MAX_SKILL_LEVEL};
+use crate::tablebase::tablebases_materials;
+use crate::tuner::{Tuner, positions_from_epd, positions_from_pgn};
 
 #[derive(Clone)]
 pub struct CLI {
     pub game: Game,
-    max_depth: Depth,
+    limits: SearchLimits,
     play_side: Option<Color>,
     pub show_board: bool,
     pub show_san: bool,
@@ -46,13 +54,22 @@ impl CLI {
     pub fn new() -> CLI {
         // Load startup position
         let mut game = Game::from_fen(DEFAULT_FEN).unwrap();
+        game.observer = Some(Arc::new(CLIObserver));
+
+        // Load endgame tablebases
+        if let Some(path) = tablebases_path() {
+            game.tablebases.load_dir(&path);
+        }
 
         // Set default clock to 40 moves in 5 minutes
-        game.clock = Clock::new(40, 5 * 60 * 1000);
+        let limits = SearchLimits {
+            clock: Some(Clock::new(40, 5 * 60 * 1000)),
+            ..SearchLimits::new()
+        };
 
         CLI {
             game,
-            max_depth: (MAX_PLY - 10) as Depth,
+            limits,
             play_side: None,
             show_board: false,
             show_san: true,
@@ -127,10 +144,16 @@ impl CLI {
                 "core" | "threads"     => self.cmd_threads(&args),
                 "hash" | "memory"      => self.cmd_memory(&args),
                 "depth"                => self.cmd_depth(&args),
+                "nodes"                => self.cmd_nodes(&args),
+                "skill"                => self.cmd_skill(&args),
+                "contempt"             => self.cmd_contempt(&args),
+                "multipv"              => self.cmd_multipv(&args),
                 "perft"                => self.cmd_perft(&args),
                 "perftsuite"           => self.cmd_perftsuite(&args),
                 "testsuite"            => self.cmd_testsuite(&args),
                 "divide"               => self.cmd_divide(&args),
+                "tablebase"            => self.cmd_tablebase(&args),
+                "tune"                 => self.cmd_tune(&args),
                 "uci"                  => self.cmd_uci(),
                 "xboard"               => self.cmd_xboard(),
                 "help" | "h"           => self.cmd_usage("help"),
@@ -178,14 +201,21 @@ impl CLI {
             "  show <feature>            Show <feature>",
             "  hide <feature>            Hide <feature>",
             "  time <moves> <time>       Set clock to <moves> in <time> (in seconds)",
+            "  time <control>            Set clock to <control> like 40/5400+30:1800",
             "  hash <size>               Set the <size> of the memory (in MB)",
             "  core <number>             Set the <number> of threads",
             "  depth <number>            Set the search depth <number>",
+            "  nodes <number>            Set the <number> of nodes to search deterministically",
+            "  skill <level>             Set the skill <level> from 0 to 20",
+            "  contempt <score>          Set the <score> of draws for the opponent (in centipawns)",
+            "  multipv <number>          Set the <number> of best lines to search",
             "",
             "  perft [<depth>]           Count the nodes at each depth",
             "  perftsuite <epd>          Compare perft results to each position of <epd>",
             "  testsuite <epd> [<time>]  Search each position of <epd> [for <time>]",
             "  divide <depth>            Count the nodes at <depth> for each moves",
+            "  tablebase [<material>]    Generate endgame tablebases [of <material>]",
+            "  tune <games> <params>     Tune eval parameters with <games> into <params>",
             "",
             "  uci                       Start UCI mode",
             "  xboard                    Start XBoard mode",
@@ -235,6 +265,7 @@ impl CLI {
         println!();
         println!("  load fen <string>         Load game from FEN <string>");
         println!("  load pgn <file>           Load game from PGN <file>");
+        println!("  load params <file>        Load eval parameters from <file>");
         println!();
         Ok(State::Running)
     }
@@ -245,6 +276,7 @@ impl CLI {
         println!();
         println!("  save fen                  Save game to FEN <string>");
         println!("  save pgn <file>           Save game to PGN <file>");
+        println!("  save params <file>        Save eval parameters to <file>");
         println!();
         Ok(State::Running)
     }
@@ -254,6 +286,8 @@ impl CLI {
         uci.game.is_debug = self.game.is_debug;
         uci.game.threads_count = self.game.threads_count;
         uci.game.tt = self.game.tt.clone();
+        uci.game.set_eval_params(self.game.eval_params.clone());
+        uci.game.tablebases = self.game.tablebases.clone();
         uci.run();
         Ok(State::Stopped)
     }
@@ -263,12 +297,16 @@ impl CLI {
         xboard.game.is_debug = self.game.is_debug;
         xboard.game.threads_count = self.game.threads_count;
         xboard.game.tt = self.game.tt.clone();
+        xboard.game.set_eval_params(self.game.eval_params.clone());
+        xboard.game.tablebases = self.game.tablebases.clone();
         xboard.run();
         Ok(State::Stopped)
     }
 
     fn cmd_init(&mut self) -> Result<State, Box<dyn Error>> {
-        self.max_depth = (MAX_PLY - 10) as Depth;
+        self.limits.depth = None;
+        self.limits.nodes = None;
+        self.limits.deterministic = false;
         self.game.clear();
         self.game.load_fen(DEFAULT_FEN)?;
 
@@ -304,6 +342,15 @@ impl CLI {
                 let pgn = PGN::from(pgn_str);
                 self.game.load_pgn(pgn);
             }
+            "params" => {
+                if args.len() == 2 {
+                    return Err("no filename given".into());
+                }
+                let path = Path::new(args[2]);
+                let params = fs::read_to_string(path)?.parse::<EvalParams>()?;
+                self.game.set_eval_params(params);
+                return Ok(State::Running);
+            }
             "help" => {
                 return self.cmd_load_usage();
             }
@@ -344,6 +391,14 @@ impl CLI {
                 }
                 write!(buffer, "{}", pgn)?;
             }
+            "params" => {
+                if args.len() == 2 {
+                    return Err("no filename given".into());
+                }
+                let path = Path::new(args[2]);
+                let mut buffer = File::create(path)?;
+                write!(buffer, "{}", self.game.eval_params)?;
+            }
             "help" => {
                 return self.cmd_save_usage();
             }
@@ -435,6 +490,9 @@ impl CLI {
         self.game.is_eval_verbose = true;
         self.game.eval();
         self.game.is_eval_verbose = false;
+
+        // The side to move would be the root side of a search
+        println!("draw:      {:>5.2}", -0.01 * self.game.contempt as f64);
         println!();
         println!("(score in pawn, relative to {})", if c == WHITE { "white" } else { "black"});
         Ok(State::Running)
@@ -502,12 +560,15 @@ impl CLI {
     fn cmd_time(&mut self, args: &[&str]) -> Result<State, Box<dyn Error>> {
         match args.len() {
             1 => { return Err("no <moves> and <time> given".into()) },
-            2 => { return Err("no <time> given".into()) },
+            2 => {
+                self.limits.clock = Some(Clock::from_time_control(args[1])?);
+                return Ok(State::Running);
+            },
             _ => {}
         }
         let moves = args[1].parse::<u16>()?;
         let time = args[2].parse::<f64>()?;
-        self.game.clock = Clock::new(moves, (time * 1000.0).round() as u64);
+        self.limits.clock = Some(Clock::new(moves, (time * 1000.0).round() as u64));
         Ok(State::Running)
     }
 
@@ -522,18 +583,11 @@ impl CLI {
         let mut moves_count = 0u64;
         let mut nodes_count = 0u64;
 
-        let side = self.game.side();
-        self.game.moves.clear();
-        while let Some(m) = self.game.next_move() {
+        for (m, r) in self.game.divide(d + 1) {
             let move_str = if self.show_san { self.game.move_to_san(m) } else { m.to_lan() };
-            self.game.make_move(m);
-            if !self.game.is_check(side) {
-                let r = self.game.perft(d);
-                println!("{} {}", move_str, r);
-                moves_count += 1;
-                nodes_count += r;
-            }
-            self.game.undo_move(m);
+            println!("{} {}", move_str, r);
+            moves_count += 1;
+            nodes_count += r;
         }
 
         println!();
@@ -542,6 +596,28 @@ impl CLI {
         Ok(State::Running)
     }
 
+    fn cmd_tablebase(&mut self, args: &[&str]) -> Result<State, Box<dyn Error>> {
+        let path = tablebases_path().ok_or("could not find data directory")?;
+        let materials = if args.len() > 1 {
+            args[1..].iter().map(|s| s.to_string()).collect()
+        } else {
+            tablebases_materials()
+        };
+
+        for material in materials {
+            let started_at = (self.game.clock.system_time)();
+            let generated = self.game.tablebases.generate(&material)?;
+            for m in &generated {
+                self.game.tablebases.save_dir(&path, m)?;
+            }
+            let s = (((self.game.clock.system_time)() - started_at) as f64) / 1000.0;
+            if !generated.is_empty() {
+                println!("tablebase {} -> {} ({:.2} s)", material, generated.join(" "), s);
+            }
+        }
+        Ok(State::Running)
+    }
+
     fn cmd_threads(&mut self, args: &[&str]) -> Result<State, Box<dyn Error>> {
         if args.len() < 2 {
             return Err("no <number> given".into());
@@ -564,7 +640,58 @@ impl CLI {
             return Err("no <depth> given".into());
         }
         let depth = args[1].parse::<usize>()?;
-        self.max_depth = depth as Depth;
+        self.limits.depth = Some(depth as Depth);
+        Ok(State::Running)
+    }
+
+    fn cmd_skill(&mut self, args: &[&str]) -> Result<State, Box<dyn Error>> {
+        if args.len() < 2 {
+            println!("{} (~{} Elo)", self.game.skill.level(), self.game.skill.elo());
+            return Ok(State::Running);
+        }
+        let level = args[1].parse::<u8>()?;
+        if level > MAX_SKILL_LEVEL {
+            return Err(format!("<level> must be at most {}", MAX_SKILL_LEVEL).into());
+        }
+        let time = (self.game.clock.system_time)() as u64;
+        self.game.skill = Skill::new(level);
+        self.game.skill.set_seed(time);
+        Ok(State::Running)
+    }
+
+    fn cmd_contempt(&mut self, args: &[&str]) -> Result<State, Box<dyn Error>> {
+        if args.len() < 2 {
+            return Err("no <score> given".into());
+        }
+        let score = args[1].parse::<Score>()?;
+        if score.abs() > 100 {
+            return Err("<score> must be between -100 and 100".into());
+        }
+        self.game.contempt = score;
+        Ok(State::Running)
+    }
+
+    fn cmd_nodes(&mut self, args: &[&str]) -> Result<State, Box<dyn Error>> {
+        if args.len() < 2 {
+            return Err("no <number> given".into());
+        }
+        // A search limited by nodes is deterministic to be reproducible,
+        // and a number of 0 removes the limit
+        let n = args[1].parse::<u64>()?;
+        self.limits.nodes = if n > 0 { Some(n) } else { None };
+        self.limits.deterministic = n > 0;
+        Ok(State::Running)
+    }
+
+    fn cmd_multipv(&mut self, args: &[&str]) -> Result<State, Box<dyn Error>> {
+        if args.len() < 2 {
+            return Err("no <number> given".into());
+        }
+        let n = args[1].parse::<usize>()?;
+        if n < 1 {
+            return Err("<number> must be at least 1".into());
+        }
+        self.game.multipv = n;
         Ok(State::Running)
     }
 
@@ -663,10 +790,12 @@ impl CLI {
             print!("{}{}{} -> ", fen, mt, moves);
 
             self.game.load_fen(fen)?;
-            self.game.clock = Clock::new(1, time * 1000);
 
-            let n = self.max_depth;
-            let best_move = self.game.search(1..n).unwrap();
+            let limits = SearchLimits {
+                clock: Some(Clock::new(1, time * 1000)),
+                ..self.limits.clone()
+            };
+            let best_move = self.game.search(limits).unwrap();
             let mut best_move_str = self.game.move_to_san(best_move);
 
             // Add `+` to move in case of check
@@ -694,14 +823,68 @@ impl CLI {
         Ok(State::Running)
     }
 
+    fn cmd_tune(&mut self, args: &[&str]) -> Result<State, Box<dyn Error>> {
+        if args.len() < 3 {
+            return Err("no <games> or <params> given".into());
+        }
+
+        // Games are read from PGN files or from EPD files with results
+        let games = fs::read_to_string(Path::new(args[1]))?;
+        let positions = if args[1].ends_with(".pgn") {
+            positions_from_pgn(&games)
+        } else {
+            positions_from_epd(&games)
+        };
+        if positions.is_empty() {
+            return Err("no positions with results found".into());
+        }
+
+        let path = Path::new(args[2]);
+        let mut tuner = Tuner::new(self.game.eval_params.clone(), positions);
+        println!("Positions: {}", tuner.len());
+        println!("Scaling:   {:.3}", tuner.optimize_k());
+        let scores = tuner.scores(&self.game.eval_params);
+        println!("Error:     {:.6}", tuner.error(&scores, tuner.k));
+
+        // Save the parameters after each iteration to be able to stop the
+        // tuning at any time
+        let mut i = 0;
+        while let Some(error) = tuner.tune_iteration() {
+            i += 1;
+            println!("Iteration {}: error {:.6}", i, error);
+            let mut buffer = File::create(path)?;
+            write!(buffer, "{}", tuner.params)?;
+        }
+        if i == 0 {
+            let mut buffer = File::create(path)?;
+            write!(buffer, "{}", tuner.params)?;
+        }
+
+        self.game.set_eval_params(tuner.params);
+        Ok(State::Running)
+    }
+
     fn think(&mut self, play: bool) {
         let c = if play { "<" } else { "#" };
-        let n = self.max_depth;
-        let r = self.game.search(1..n);
+        let lines = self.game.search_lines(self.limits.clone());
         if self.game.is_debug || self.game.is_search_verbose {
             println!();
         }
-        if let Some(m) = r {
+
+        // Show the other best lines when searching for a hint
+        if !play && lines.len() > 1 {
+            for line in &lines {
+                let m = line.best_move;
+                let s = if self.show_san { self.game.move_to_san(m) } else { m.to_lan() };
+                match mate_in(line.score) {
+                    Some(n) => println!("{} move {} (#{})", c, s, n),
+                    None => println!("{} move {} ({:.2})", c, s, 0.01 * line.score as f64),
+                }
+            }
+            return;
+        }
+
+        if let Some(m) = lines.first().map(|line| line.best_move) {
             println!("{} move {}", c, if self.show_san { self.game.move_to_san(m) } else { m.to_lan() });
 
             if play {
@@ -740,6 +923,49 @@ fn history_path() -> Option<PathBuf> {
     }
 }
 
+fn tablebases_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|data_dir| data_dir.join("littlewing").join("tablebases"))
+}
+
+/// Print the progress of the search in CLI format
+pub struct CLIObserver;
+
+impl SearchObserver for CLIObserver {
+    fn on_start(&self, game: &Game) {
+        if game.is_search_verbose {
+            print_thinking_header();
+        }
+    }
+
+    fn on_line(&self, game: &Game, info: &SearchInfo) {
+        // Split PV over multiple lines of 80 chars max
+        let mut width = 34;
+        let mut lines = Vec::new();
+        let mut line = Vec::new();
+        let pv = thinking_pv(game, info);
+        for chunk in pv.split(' ').collect::<Vec<&str>>().chunks(3) {
+            let s = chunk.join(" ");
+            if width + s.len() >= 80 {
+                width = 34;
+                lines.push(line.join(" "));
+                line.clear();
+            }
+            width += s.len() + 1;
+            line.push(s);
+        }
+        if !line.is_empty() {
+            lines.push(line.join(" "));
+        }
+        let pv = lines.join(&format!("{:<34}", "\n"));
+
+        let score = match mate_in(info.score) {
+            Some(n) => format!("#{}", n),
+            None => format!("{}", info.score),
+        };
+        println!("  {:>3}  {:>5}  {:>6}  {:>9}  {}", info.depth, score, info.time / 10, info.nodes, pv);
+    }
+}
+
 #[derive(Helper, Validator, Highlighter, Hinter)]
 struct CommandHelper {
     move_params: Vec<String>
@@ -752,12 +978,12 @@ impl Completer for CommandHelper {
         let move_params = self.move_params.iter().map(AsRef::as_ref).collect();
         let play_params = vec!["black", "white", "none"];
         let conf_params = vec!["board", "color", "coord", "debug", "think", "san"];
-        let load_params = vec!["fen", "pgn", "help"];
-        let save_params = vec!["fen", "pgn", "help"];
+        let load_params = vec!["fen", "pgn", "params", "help"];
+        let save_params = vec!["fen", "pgn", "params", "help"];
         let commands = vec![
             "help", "quit", "init", "load", "save", "play", "hint", "eval",
-            "undo", "move", "time", "show", "hide", "core", "hash", "perft",
-            "perftsuite", "testsuite", "divide", "xboard", "uci"
+            "undo", "move", "time", "show", "hide", "core", "hash", "nodes", "skill", "contempt", "multipv", "perft",
+            "perftsuite", "testsuite", "divide", "tablebase", "tune", "xboard", "uci"
         ];
 
         let options = vec![
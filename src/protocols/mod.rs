@@ -1,11 +1,24 @@
+use std::prelude::v1::*;
+
+use crate::game::Game;
+use crate::search_observer::{Bound, SearchInfo, pv_to_san};
+
 pub mod cli;
 pub mod uci;
 pub mod xboard;
 
-#[repr(u8)]
-#[derive(Clone, Eq, PartialEq)]
-pub enum Protocol {
-    CLI,
-    UCI,
-    XBoard
+// Print the header of the thinking output shared by XBoard and the CLI
+fn print_thinking_header() {
+    println!("  {:>3}  {:>5}  {:>6}  {:>9}  pv", "ply", "score", "time", "nodes");
+}
+
+// Get the PV of a line in SAN with the marks of fail-high and fail-low
+// lines used in the thinking output of XBoard and the CLI
+fn thinking_pv(game: &Game, info: &SearchInfo) -> String {
+    let pv = pv_to_san(game, &info.pv);
+    match info.bound {
+        Bound::Exact => pv,
+        Bound::Lower => format!("{} ++", pv),
+        Bound::Upper => format!("{} --", pv),
+    }
 }
@@ -1,4 +1,6 @@
 use std::prelude::v1::*;
+use std::cmp;
+use std::fs;
 use std::io;
 use std::thread;
 use std::sync::Arc;
@@ -7,35 +9,56 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use crate::color::*;
 use crate::common::*;
 use crate::clock::Clock;
+use crate::eval_params::EvalParams;
 use crate::fen::FEN;
 use crate::game::Game;
+use crate::piece_move::PieceMove;
 use crate::piece_move_generator::PieceMoveGenerator;
 use crate::piece_move_notation::PieceMoveNotation;
-use crate::search::Search;
-use crate::protocols::Protocol;
+use crate::search::{Search, mate_in};
+use crate::search_limits::SearchLimits;
+use crate::search_observer::{Bound, SearchInfo, SearchObserver, pv_to_lan};
+use crate::skill::{Skill, MIN_ELO, MAX_ELO};
 use crate::version;
 
+// Time in milliseconds after which the root moves searched are printed
+const CURRMOVE_DELAY: u64 = 1000;
+
 pub struct UCI {
     pub game: Game,
-    max_depth: Depth,
     searcher: Option<thread::JoinHandle<()>>,
     print_bestmove: Arc<AtomicBool>,
+    move_overhead: u64,
+    deterministic: bool,
+    limit_strength: bool,
+    elo: u16,
 }
 
 impl UCI {
     pub fn new() -> UCI {
         UCI {
             game: Game::from_fen(DEFAULT_FEN).unwrap(),
-            max_depth: (MAX_PLY - 10) as Depth,
             searcher: None,
-            print_bestmove: Arc::new(AtomicBool::new(false))
+            print_bestmove: Arc::new(AtomicBool::new(false)),
+            move_overhead: 0,
+            deterministic: false,
+            limit_strength: false,
+            elo: MAX_ELO,
         }
     }
     pub fn run(&mut self) {
-        self.game.protocol = Protocol::UCI;
+        self.game.observer = Some(Arc::new(UCIObserver));
         self.game.is_search_verbose = true;
         println!("id name {}", version());
         println!("id author Vincent Ollivier");
+        println!("option name MultiPV type spin default 1 min 1 max 256");
+        println!("option name Ponder type check default false");
+        println!("option name Move Overhead type spin default 0 min 0 max 5000");
+        println!("option name Deterministic type check default false");
+        println!("option name Contempt type spin default 0 min -100 max 100");
+        println!("option name EvalParams type string default <empty>");
+        println!("option name UCI_LimitStrength type check default false");
+        println!("option name UCI_Elo type spin default {} min {} max {}", MAX_ELO, MIN_ELO, MAX_ELO);
         println!("uciok");
         loop {
             let mut cmd = String::new();
@@ -44,8 +67,10 @@ impl UCI {
             match args[0] {
                 "quit"       => break,
                 "stop"       => self.cmd_stop(),
+                "ponderhit"  => self.cmd_ponderhit(),
                 "isready"    => self.cmd_isready(),
                 "ucinewgame" => self.cmd_ucinewgame(),
+                "setoption"  => self.cmd_setoption(&args),
                 "position"   => self.cmd_position(&args),
                 "go"         => self.cmd_go(&args),
                 _            => continue, // Ignore unknown commands
@@ -58,6 +83,10 @@ impl UCI {
         self.stop_search();
     }
 
+    fn cmd_ponderhit(&mut self) {
+        self.game.clock.ponderhit();
+    }
+
     fn cmd_isready(&mut self) {
         println!("readyok");
     }
@@ -65,52 +94,178 @@ impl UCI {
     fn cmd_ucinewgame(&mut self) {
         self.abort_search();
 
-        self.max_depth = (MAX_PLY - 10) as Depth;
         self.game.clear();
     }
 
+    fn cmd_setoption(&mut self, args: &[&str]) {
+        self.abort_search();
+
+        let mut is_name = false;
+        let mut is_value = false;
+        let mut name = Vec::with_capacity(args.len());
+        let mut value = Vec::with_capacity(args.len());
+        for &arg in args.iter().skip(1) {
+            match arg {
+                "name" => { // Next args will form the option name
+                    is_name = true;
+                    is_value = false;
+                },
+                "value" => { // Next args will form the option value
+                    is_name = false;
+                    is_value = true;
+                },
+                _ => {
+                    if is_name {
+                        name.push(arg);
+                    } else if is_value {
+                        value.push(arg);
+                    }
+                }
+            }
+        }
+
+        // Option names are case insensitive
+        let name = name.join(" ").to_lowercase();
+        let value = value.join(" ");
+        if name == "multipv" {
+            if let Ok(n) = value.parse::<usize>() {
+                self.game.multipv = n.clamp(1, 256);
+            }
+        } else if name == "move overhead" {
+            if let Ok(t) = value.parse::<u64>() {
+                self.move_overhead = t.min(5000);
+            }
+        } else if name == "deterministic" {
+            if let Ok(b) = value.to_lowercase().parse::<bool>() {
+                self.deterministic = b;
+            }
+        } else if name == "contempt" {
+            if let Ok(n) = value.parse::<Score>() {
+                self.game.contempt = n.clamp(-100, 100);
+            }
+        } else if name == "evalparams" {
+            if value.is_empty() || value == "<empty>" {
+                self.game.set_eval_params(EvalParams::default());
+            } else {
+                match fs::read_to_string(&value).map_err(|e| e.to_string()).and_then(|s| s.parse()) {
+                    Ok(params) => self.game.set_eval_params(params),
+                    Err(e) => println!("info string could not load eval params: {}", e),
+                }
+            }
+        } else if name == "uci_limitstrength" {
+            if let Ok(b) = value.to_lowercase().parse::<bool>() {
+                self.limit_strength = b;
+            }
+        } else if name == "uci_elo" {
+            if let Ok(elo) = value.parse::<u16>() {
+                self.elo = elo.clamp(MIN_ELO, MAX_ELO);
+            }
+        }
+        self.game.skill = if self.limit_strength { Skill::from_elo(self.elo) } else { Skill::default() };
+    }
+
     fn cmd_go(&mut self, args: &[&str]) {
         self.abort_search();
 
         let side = self.game.side();
+        let mut limits = SearchLimits {
+            deterministic: self.deterministic,
+            ..SearchLimits::new()
+        };
         let mut time = u64::max_value(); // Infinite time
+        let mut increment = 0;
         let mut moves = 0;
-        let mut next_arg_is_time = false;
-        let mut next_arg_is_moves = false;
-        for &arg in args {
+        let mut is_ponder = false;
+        let mut is_searchmoves = false;
+        let mut params = args.iter().skip(1);
+        while let Some(&arg) = params.next() {
             match arg {
                 "wtime" => {
-                    if side == WHITE {
-                        next_arg_is_time = true;
+                    if let Some(t) = params.next().and_then(|s| s.parse().ok()) {
+                        if side == WHITE {
+                            time = t;
+                        }
                     }
                 },
                 "btime" => {
-                    if side == BLACK {
-                        next_arg_is_time = true;
+                    if let Some(t) = params.next().and_then(|s| s.parse().ok()) {
+                        if side == BLACK {
+                            time = t;
+                        }
+                    }
+                },
+                "winc" => {
+                    if let Some(t) = params.next().and_then(|s| s.parse().ok()) {
+                        if side == WHITE {
+                            increment = t;
+                        }
+                    }
+                },
+                "binc" => {
+                    if let Some(t) = params.next().and_then(|s| s.parse().ok()) {
+                        if side == BLACK {
+                            increment = t;
+                        }
                     }
                 },
-                "movetime" => {
-                    next_arg_is_time = true;
-                }
                 "movestogo" => {
-                    next_arg_is_moves = true;
+                    moves = params.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+                },
+                "movetime" => {
+                    limits.movetime = params.next().and_then(|s| s.parse().ok());
+                },
+                "depth" => {
+                    limits.depth = params.next().and_then(|s| s.parse().ok());
+                },
+                "nodes" => {
+                    limits.nodes = params.next().and_then(|s| s.parse().ok());
+                },
+                "mate" => {
+                    limits.mate = params.next().and_then(|s| s.parse().ok());
+                },
+                "infinite" => {
+                    limits.infinite = true;
+                },
+                "ponder" => {
+                    is_ponder = true;
+                },
+                "searchmoves" => { // Next args will form the moves list
+                    is_searchmoves = true;
+                    continue;
                 },
                 _ => {
-                    if next_arg_is_time {
-                        time = arg.parse::<u64>().unwrap();
-                        next_arg_is_time = false;
-                    } else if next_arg_is_moves {
-                        moves = arg.parse::<u16>().unwrap();
-                        next_arg_is_moves = false;
+                    if is_searchmoves {
+                        let m = self.game.move_from_lan(arg);
+                        limits.searchmoves.push(m);
+                        continue;
                     }
                 }
             }
+            is_searchmoves = false;
+        }
+
+        // The number of moves to play is computed from the moves history
+        // in sudden death when it's not given
+        let mut clock = Clock::new(moves, time);
+        if moves > 0 {
+            clock.disable_level();
+        }
+        clock.set_increment(increment);
+        clock.set_move_overhead(self.move_overhead);
+        if is_ponder {
+            clock.ponder();
         }
-        // FIXME: time increment is ignored
-        self.game.clock = Clock::new(moves, time);
-        self.game.clock.disable_level();
+
+        // The game is cloned by the search so the skill is seeded each time
+        // to avoid playing the same weaker moves
+        self.game.skill.set_seed((clock.system_time)() as u64);
+
+        // Share the clock with the search to handle `ponderhit`
+        self.game.clock = clock.clone();
+        limits.clock = Some(clock);
+
         self.print_bestmove.store(true, Ordering::Relaxed);
-        self.start_search();
+        self.start_search(limits);
     }
 
     fn cmd_position(&mut self, args: &[&str]) {
@@ -152,8 +307,11 @@ impl UCI {
         }
     }
 
-    fn start_search(&mut self) {
-        let n = self.max_depth;
+    fn start_search(&mut self, limits: SearchLimits) {
+        // Forget a stop sent after the end of the previous search, but keep
+        // the ones sent from now on even before the start of the search
+        self.game.search_handle().clear();
+
         let mut game = self.game.clone();
         let print_bestmove = self.print_bestmove.clone();
 
@@ -162,22 +320,27 @@ impl UCI {
             stack_size(4 << 20);
 
         self.searcher = Some(builder.spawn(move || {
-            let res = game.search(1..n);
+            let lines = game.search_lines(limits);
 
             if print_bestmove.load(Ordering::Relaxed) {
-                match res {
-                    Some(m) => println!("bestmove {}", m.to_lan()),
-                    None    => println!("bestmove 0000")
+                match lines.first() {
+                    Some(line) => {
+                        // Suggest the expected reply to ponder on
+                        match line.pv.get(1) {
+                            Some(m) => println!("bestmove {} ponder {}", line.best_move.to_lan(), m.to_lan()),
+                            None    => println!("bestmove {}", line.best_move.to_lan())
+                        }
+                    },
+                    None => println!("bestmove 0000")
                 }
             }
         }).unwrap());
     }
 
     fn stop_search(&mut self) {
-        self.game.clock.stop();
-
         // Wait for current search to end
         if let Some(searcher) = self.searcher.take() {
+            self.game.search_handle().stop();
             searcher.join().unwrap();
         }
     }
@@ -187,3 +350,86 @@ impl UCI {
         self.stop_search();
     }
 }
+
+/// Print the progress of the search in UCI format
+pub struct UCIObserver;
+
+impl SearchObserver for UCIObserver {
+    fn on_line(&self, game: &Game, info: &SearchInfo) {
+        let bound = match info.bound {
+            Bound::Exact => "",
+            Bound::Lower => " lowerbound",
+            Bound::Upper => " upperbound",
+        };
+        let multipv = if game.multipv > 1 || game.skill.is_enabled() {
+            format!(" multipv {}", info.multipv)
+        } else {
+            String::new()
+        };
+        let score = match mate_in(info.score) {
+            Some(n) => format!("mate {}", n),
+            None => format!("cp {}", info.score),
+        };
+        let nps = info.nodes * 1000 / cmp::max(info.time, 1);
+        let pv = pv_to_lan(&info.pv);
+        println!(
+            "info depth {} seldepth {}{} score {}{} time {} nodes {} nps {} hashfull {} tbhits {} pv {}",
+            info.depth, info.seldepth, multipv, score, bound, info.time, info.nodes, nps, info.hashfull, info.tbhits, pv
+        );
+    }
+
+    fn on_current_move(&self, game: &Game, depth: Depth, m: PieceMove, number: usize) {
+        if !game.is_search_verbose || game.clock.elapsed_time() < CURRMOVE_DELAY {
+            return;
+        }
+        println!("info depth {} currmove {} currmovenumber {}", depth, m.to_lan(), number);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn wait_for_search(uci: &UCI) {
+        while !uci.searcher.as_ref().unwrap().is_finished() {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_go_twice() {
+        let mut uci = UCI::new();
+        let handle = uci.game.search_handle();
+
+        uci.cmd_position(&["position", "startpos"]);
+        uci.cmd_go(&["go", "movetime", "100"]);
+        wait_for_search(&uci);
+        assert!(handle.best_move().is_some());
+
+        // The end of the first search is followed by a stop request that
+        // should not stop the second search
+        uci.cmd_position(&["position", "startpos", "moves", "e2e4"]);
+        uci.cmd_go(&["go", "movetime", "100"]);
+        wait_for_search(&uci);
+        assert!(handle.best_move().is_some());
+        uci.cmd_stop();
+    }
+
+    #[test]
+    fn test_go_infinite_and_stop() {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut uci = UCI::new();
+            uci.cmd_position(&["position", "startpos"]);
+            for _ in 0..10 {
+                // The stop could be sent before the start of the search
+                uci.cmd_go(&["go", "infinite"]);
+                uci.cmd_stop();
+            }
+            sender.send(()).unwrap();
+        });
+        assert!(receiver.recv_timeout(Duration::from_secs(10)).is_ok());
+    }
+}
@@ -1,5 +1,7 @@
 use std::prelude::v1::*;
 use std::io;
+use std::thread;
+use std::sync::Arc;
 use regex::Regex;
 
 use crate::color::*;
@@ -8,16 +10,22 @@ use crate::attack::Attack;
 use crate::clock::Clock;
 use crate::fen::FEN;
 use crate::game::Game;
+use crate::piece_move::PieceMove;
 use crate::piece_move_generator::PieceMoveGenerator;
 use crate::piece_move_notation::PieceMoveNotation;
-use crate::search::Search;
-use crate::protocols::Protocol;
+use crate::search::{Search, SearchLine, mate_in};
+use crate::search_limits::SearchLimits;
+use crate::search_observer::{SearchInfo, SearchObserver};
+use crate::skill::{Skill, MAX_SKILL_LEVEL};
+use crate::protocols::{print_thinking_header, thinking_pv};
 use crate::version;
 
 pub struct XBoard {
     pub game: Game,
     max_depth: Depth,
-    force: bool
+    force: bool,
+    ponder: bool,
+    pondering: Option<(PieceMove, thread::JoinHandle<Vec<SearchLine>>)>
 }
 
 impl XBoard {
@@ -25,11 +33,13 @@ impl XBoard {
         XBoard {
             game: Game::from_fen(DEFAULT_FEN).unwrap(),
             max_depth: (MAX_PLY - 10) as Depth,
-            force: false
+            force: false,
+            ponder: false,
+            pondering: None
         }
     }
     pub fn run(&mut self) {
-        self.game.protocol = Protocol::XBoard;
+        self.game.observer = Some(Arc::new(XBoardObserver));
         println!(""); // Acknowledge XBoard mode
         loop {
             let mut line = String::new();
@@ -37,6 +47,8 @@ impl XBoard {
             let args: Vec<&str> = line.trim().split(' ').collect();
             match args[0] {
                 "quit"     => break,
+                "hard"     => self.cmd_hard(),
+                "easy"     => self.cmd_easy(),
                 "force"    => self.cmd_force(),
                 "new"      => self.cmd_new(),
                 "go"       => self.cmd_go(),
@@ -52,22 +64,36 @@ impl XBoard {
                 "sd"       => self.cmd_depth(&args),
                 "level"    => self.cmd_level(&args),
                 "protover" => self.cmd_protover(&args),
+                "option"   => self.cmd_option(&args),
                 _          => self.parse_move(&args)
             }
         }
+        self.stop_pondering();
+    }
+
+    fn cmd_hard(&mut self) {
+        self.ponder = true;
+    }
+
+    fn cmd_easy(&mut self) {
+        self.stop_pondering();
+        self.ponder = false;
     }
 
     fn cmd_force(&mut self) {
+        self.stop_pondering();
         self.force = true;
     }
 
     fn cmd_new(&mut self) {
+        self.stop_pondering();
         self.max_depth = (MAX_PLY - 10) as Depth;
         self.game.clear();
         self.game.load_fen(DEFAULT_FEN).unwrap();
     }
 
     fn cmd_go(&mut self) {
+        self.stop_pondering();
         self.force = false;
         self.think();
     }
@@ -81,6 +107,7 @@ impl XBoard {
     }
 
     fn cmd_undo(&mut self) {
+        self.stop_pondering();
         if self.game.history.len() > 0 {
             let m = self.game.history.pop().unwrap();
             self.game.undo_move(m);
@@ -88,6 +115,7 @@ impl XBoard {
     }
 
     fn cmd_remove(&mut self) {
+        self.stop_pondering();
         let m = self.game.history.pop().unwrap();
         self.game.undo_move(m);
 
@@ -112,11 +140,13 @@ impl XBoard {
 
         let fen = args[1..].join(" ");
 
+        self.stop_pondering();
         self.game.clear();
         self.game.load_fen(&fen).unwrap();
     }
 
     fn cmd_level(&mut self, args: &[&str]) {
+        self.stop_pondering();
         let moves = args[1].parse::<u16>().unwrap();
 
         // `time` is given in `mm:ss` or `ss`.
@@ -126,31 +156,51 @@ impl XBoard {
             None    => args[2].parse::<u64>().unwrap()
         };
 
-        // FIXME: time increment is ignored
+        // `inc` is given in seconds
+        let increment = args.get(3).and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
 
         self.game.clock = Clock::new(moves, time * 1000);
+        self.game.clock.set_increment((increment * 1000.0).round() as u64);
     }
 
     fn cmd_depth(&mut self, args: &[&str]) {
-        self.max_depth = args[1].parse::<Depth>().unwrap() + 1;
+        self.max_depth = args[1].parse::<Depth>().unwrap();
     }
 
     fn cmd_memory(&mut self, args: &[&str]) {
+        self.stop_pondering();
         let memory = args[1].parse::<usize>().unwrap(); // In MB
         self.game.tt_resize(memory << 20);
     }
 
     fn cmd_cores(&mut self, args: &[&str]) {
+        self.stop_pondering();
         self.game.threads_count = args[1].parse::<usize>().unwrap();
     }
 
     #[allow(unused_variables)] // TODO: remove that
     fn cmd_protover(&mut self, args: &[&str]) {
         println!("feature myname=\"{}\"", version());
+        println!("feature option=\"Skill Level -spin {} 0 {}\"", MAX_SKILL_LEVEL, MAX_SKILL_LEVEL);
         println!("feature sigint=0 ping=1 setboard=1 memory=1 smp=1 done=1");
         // TODO: check that the features got accepted
     }
 
+    fn cmd_option(&mut self, args: &[&str]) {
+        // Options are given as `option <name>=<value>`
+        let option = args[1..].join(" ");
+        if let Some((name, value)) = option.split_once('=') {
+            if name == "Skill Level" {
+                if let Ok(level) = value.parse::<u8>() {
+                    self.stop_pondering();
+                    let time = (self.game.clock.system_time)() as u64;
+                    self.game.skill = Skill::new(level);
+                    self.game.skill.set_seed(time);
+                }
+            }
+        }
+    }
+
     fn parse_move(&mut self, args: &[&str]) {
         let re = Regex::new(r"^[a-h][0-9][a-h][0-9][nbrq]?$").unwrap();
         if !re.is_match(args[0]) {
@@ -158,17 +208,46 @@ impl XBoard {
         }
 
         let m = self.game.move_from_lan(args[0]);
+
+        // Use the result of the search done while pondering if the opponent
+        // played the expected move, or discard it
+        let lines = match self.pondering.take() {
+            Some((reply, searcher)) if reply == m => {
+                self.game.clock.ponderhit();
+                Some(searcher.join().unwrap())
+            },
+            Some((_, searcher)) => {
+                self.game.clock.ponderhit();
+                self.game.search_handle().stop();
+                searcher.join().unwrap();
+                None
+            },
+            None => None
+        };
+
         self.game.make_move(m);
         self.game.history.push(m);
 
         if !self.force {
-            self.think();
+            match lines {
+                Some(lines) => self.play(lines),
+                None => self.think()
+            }
         }
     }
 
     fn think(&mut self) {
-        let n = self.max_depth;
-        match self.game.search(1..n) {
+        self.game.search_handle().clear();
+        let limits = SearchLimits {
+            depth: Some(self.max_depth),
+            ..SearchLimits::new()
+        };
+        let lines = self.game.search_lines(limits);
+        self.play(lines);
+    }
+
+    fn play(&mut self, lines: Vec<SearchLine>) {
+        match lines.first().map(|line| line.best_move) {
             None => {
                 if self.game.is_check(WHITE) {
                     println!("0-1 {{black mates}}");
@@ -183,7 +262,71 @@ impl XBoard {
                 self.game.history.push(m);
 
                 println!("move {}", m.to_lan());
+
+                // Search the expected reply on the opponent's time
+                if self.ponder {
+                    if let Some(&reply) = lines[0].pv.get(1) {
+                        self.start_pondering(reply);
+                    }
+                }
             }
         }
     }
+
+    fn start_pondering(&mut self, reply: PieceMove) {
+        // Forget a stop sent after the end of the previous search, but keep
+        // the ones sent from now on even before the start of the search
+        self.game.search_handle().clear();
+
+        let mut game = self.game.clone();
+        game.make_move(reply);
+        game.history.push(reply);
+
+        // The clock is shared with the game to handle `ponderhit`
+        game.clock.ponder();
+
+        let limits = SearchLimits {
+            depth: Some(self.max_depth),
+            ..SearchLimits::new()
+        };
+
+        let builder = thread::Builder::new().
+            name(String::from("ponderer")).
+            stack_size(4 << 20);
+
+        let searcher = builder.spawn(move || {
+            game.search_lines(limits)
+        }).unwrap();
+
+        self.pondering = Some((reply, searcher));
+    }
+
+    fn stop_pondering(&mut self) {
+        if let Some((_, searcher)) = self.pondering.take() {
+            self.game.clock.ponderhit();
+            self.game.search_handle().stop();
+            searcher.join().unwrap();
+        }
+    }
+}
+
+/// Print the progress of the search in XBoard format
+pub struct XBoardObserver;
+
+impl SearchObserver for XBoardObserver {
+    fn on_start(&self, game: &Game) {
+        if game.is_search_verbose {
+            print_thinking_header();
+        }
+    }
+
+    fn on_line(&self, game: &Game, info: &SearchInfo) {
+        // XBoard expects mate scores as 100000 + N for mate in N moves
+        let score = match mate_in(info.score) {
+            Some(n) => n.signum() as i32 * (100000 + n.abs() as i32),
+            None => info.score as i32,
+        };
+        let pv = thinking_pv(game, info);
+        println!("  {:>3}  {:>5}  {:>6}  {:>9}  {}", info.depth, score, info.time / 10, info.nodes, pv);
+    }
 }
@@ -0,0 +1,90 @@
+use std::prelude::v1::*;
+
+use crate::common::*;
+use crate::piece_move::PieceMove;
+
+/// Triangular table of the principal variations found at each ply
+///
+/// The PV of a node is made of its best move followed by the PV of the child
+/// node found by the search of this move, so each ply only needs to store
+/// the moves from its own ply to the end of the line.
+#[derive(Clone)]
+pub struct PvTable {
+    moves: Vec<[PieceMove; MAX_PLY]>, // Kept on the heap to keep games small
+    ends: [usize; MAX_PLY],
+}
+
+impl PvTable {
+    pub fn new() -> PvTable {
+        let mut pv = PvTable {
+            moves: vec![[PieceMove::new_null(); MAX_PLY]; MAX_PLY],
+            ends: [0; MAX_PLY],
+        };
+        for ply in 0..MAX_PLY {
+            pv.clear(ply);
+        }
+        pv
+    }
+
+    /// Clear the PV of the given ply
+    pub fn clear(&mut self, ply: usize) {
+        if ply < MAX_PLY {
+            self.ends[ply] = ply;
+        }
+    }
+
+    /// Set the PV of the given ply to the move followed by the PV of the
+    /// next ply
+    pub fn update(&mut self, ply: usize, m: PieceMove) {
+        if ply >= MAX_PLY {
+            return;
+        }
+        self.moves[ply][ply] = m;
+        self.ends[ply] = ply + 1;
+        if ply + 1 < MAX_PLY {
+            let end = self.ends[ply + 1];
+            let (row, next_rows) = self.moves.split_at_mut(ply + 1);
+            row[ply][(ply + 1)..end].copy_from_slice(&next_rows[0][(ply + 1)..end]);
+            self.ends[ply] = end;
+        }
+    }
+
+    /// Get the PV of the given ply
+    pub fn get(&self, ply: usize) -> &[PieceMove] {
+        &self.moves[ply][ply..self.ends[ply]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::square::*;
+
+    #[test]
+    fn test_pv_table() {
+        let mut pv = PvTable::new();
+        let a = PieceMove::new(E2, E4, QUIET_MOVE);
+        let b = PieceMove::new(E7, E5, QUIET_MOVE);
+        let c = PieceMove::new(G1, F3, QUIET_MOVE);
+        let d = PieceMove::new(D2, D4, QUIET_MOVE);
+        let e = PieceMove::new(D7, D5, QUIET_MOVE);
+
+        pv.clear(0);
+        assert!(pv.get(0).is_empty());
+
+        pv.clear(1);
+        pv.clear(2);
+        pv.update(2, c);
+        pv.update(1, b);
+        pv.update(0, a);
+        assert_eq!(pv.get(0), &[a, b, c]);
+
+        // A better move with a shorter line replaces the PV
+        pv.clear(1);
+        pv.clear(2);
+        pv.update(1, e);
+        pv.update(0, d);
+        assert_eq!(pv.get(0), &[d, e]);
+        assert_eq!(pv.get(1), &[e]);
+    }
+}
@@ -1,36 +1,262 @@
 use std::prelude::v1::*;
 use std::cmp;
 use std::ops::Range;
+use std::sync::Arc;
 
 #[cfg(feature = "std")]
 use std::thread;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::time::Duration;
 
 use crate::color::*;
 use crate::piece::*;
+use crate::square::*;
 use crate::common::*;
 use crate::attack::Attack;
-use crate::bitboard::BitboardExt;
+use crate::bitboard::{Bitboard, BitboardExt, BitboardIterator};
 use crate::eval::Eval;
 #[cfg(feature = "std")]
 use crate::fen::FEN;
 use crate::game::Game;
+use crate::hyperbola::{bishop_attacks, rook_attacks};
+use crate::perft_table::PerftTable;
 use crate::piece_move::PieceMove;
 use crate::piece_move_generator::PieceMoveGenerator;
-use crate::piece_move_notation::PieceMoveNotation;
+use crate::search_handle::SearchHandle;
+use crate::search_limits::SearchLimits;
+use crate::search_observer::SearchInfo;
+use crate::tablebase::Dtm;
 use crate::transposition::Bound;
+
+// Size of the initial aspiration window around the previous score
+const ASPIRATION_WINDOW: Score = 25;
+
+// Minimum depth at which aspiration windows are used
+const ASPIRATION_DEPTH: Depth = 5;
+
+// Minimum depth at which the time allocated to a move can be adjusted
+const TIME_MANAGEMENT_DEPTH: Depth = 4;
+
+// Size of the hash table of perft sub-trees
+const PERFT_TABLE_SIZE: usize = 32 << 20; // 32 MB
+
+// Minimum weight of the vote of a thread for its best move
+#[cfg(feature = "std")]
+const VOTE_WEIGHT: i32 = 10;
+
+/// Get the number of moves to mate from a score of the side to move, or a
+/// negative number if the side to move will be mated
+pub fn mate_in(score: Score) -> Option<Score> {
+    let inf = INF - (MAX_PLY as Score);
+    if score > inf {
+        Some((INF - score + 1) / 2)
+    } else if score < -inf {
+        Some(-(INF + score) / 2)
+    } else {
+        None
+    }
+}
+
+/// A line found by the search from the root position
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchLine {
+    /// First move of the line
+    pub best_move: PieceMove,
+
+    /// Score of the line from the side to move
+    pub score: Score,
+
+    /// Depth of the iteration that found the line
+    pub depth: Depth,
+
+    /// Principal variation starting with the best move
+    pub pv: Vec<PieceMove>,
+}
+
+// Vote for the best move among the lines found by the threads of a search
+//
+// Only the threads having completed the deepest iteration can vote, and each
+// vote is weighted by the score of the thread above the lowest score of the
+// voters, plus a minimum weight. The lines of the best thread voting for the
+// elected move are kept, and the first threads are preferred in case of
+// equality.
 #[cfg(feature = "std")]
-use crate::protocols::Protocol;
+fn vote_lines(results: Vec<Vec<SearchLine>>) -> Vec<SearchLine> {
+    let firsts: Vec<&SearchLine> = results.iter().filter_map(|lines| lines.first()).collect();
+    let depth = match firsts.iter().map(|line| line.depth).max() {
+        Some(depth) => depth,
+        None => return Vec::new(),
+    };
+    let voters: Vec<&SearchLine> = firsts.into_iter().filter(|line| line.depth == depth).collect();
+    let min_score = voters.iter().map(|line| line.score as i32).min().unwrap();
+
+    let mut votes: Vec<(PieceMove, i32)> = Vec::with_capacity(voters.len());
+    for line in &voters {
+        let weight = line.score as i32 - min_score + VOTE_WEIGHT;
+        match votes.iter_mut().find(|(m, _)| *m == line.best_move) {
+            Some((_, n)) => *n += weight,
+            None => votes.push((line.best_move, weight)),
+        }
+    }
+    let mut elected = votes[0];
+    for &vote in &votes[1..] {
+        if vote.1 > elected.1 {
+            elected = vote;
+        }
+    }
+
+    let mut best: Option<Vec<SearchLine>> = None;
+    for lines in results {
+        let is_better = match (lines.first(), best.as_ref().and_then(|b| b.first())) {
+            (Some(line), _) if line.depth != depth || line.best_move != elected.0 => false,
+            (Some(line), Some(other)) => line.score > other.score,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if is_better {
+            best = Some(lines);
+        }
+    }
+    best.unwrap()
+}
+
+/// Search extensions that can be switched on and off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchExtensions {
+    /// Extend moves giving check
+    pub check: bool,
+
+    /// Extend the only legal move when in check
+    pub one_reply: bool,
+
+    /// Extend captures recapturing on the square of the previous capture
+    pub recapture: bool,
+
+    /// Extend the move from the transposition table when all the other moves
+    /// fail low with a reduced search
+    pub singular: bool,
+}
+
+impl Default for SearchExtensions {
+    fn default() -> Self {
+        SearchExtensions {
+            check: true,
+            one_reply: true,
+            recapture: true,
+            singular: true,
+        }
+    }
+}
+
+/// Margins of the static pruning techniques of the search
+///
+/// The margins are given in centipawns for each remaining ply of depth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PruningMargins {
+    /// Skip quiet moves that can't raise the material above alpha
+    pub futility: Score,
+
+    /// Maximum depth of futility pruning
+    pub futility_depth: Depth,
+
+    /// Return the static evaluation of nodes that are too far above beta
+    pub reverse_futility: Score,
+
+    /// Maximum depth of reverse futility pruning
+    pub reverse_futility_depth: Depth,
+
+    /// Verify with a quiescence search the nodes that are too far below alpha
+    pub razoring: Score,
+
+    /// Maximum depth of razoring
+    pub razoring_depth: Depth,
+}
+
+impl Default for PruningMargins {
+    fn default() -> Self {
+        PruningMargins {
+            futility: 100,
+            futility_depth: 5,
+            reverse_futility: 120,
+            reverse_futility_depth: 6,
+            razoring: 300,
+            razoring_depth: 3,
+        }
+    }
+}
+
+/// Statistics of the last search
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchStats {
+    /// Number of moves extended because they were giving check
+    pub check_extensions: u64,
+
+    /// Number of moves extended because they were the only legal reply
+    pub one_reply_extensions: u64,
+
+    /// Number of moves extended because they were recaptures
+    pub recapture_extensions: u64,
+
+    /// Number of moves extended because they were singular
+    pub singular_extensions: u64,
+
+    /// Maximum ply reached by the search
+    pub seldepth: usize,
+
+    /// Number of positions found in the endgame tablebases
+    pub tbhits: u64,
+}
+
+impl SearchStats {
+    /// Get the number of moves extended
+    pub fn extensions(&self) -> u64 {
+        self.check_extensions +
+        self.one_reply_extensions +
+        self.recapture_extensions +
+        self.singular_extensions
+    }
+
+    #[cfg(feature = "std")]
+    pub fn print(&self) {
+        let n = self.extensions();
+        println!("# {:15} {:>8}", "extensions:", n);
+        let lines = [
+            (" - check:", self.check_extensions),
+            (" - one reply:", self.one_reply_extensions),
+            (" - recapture:", self.recapture_extensions),
+            (" - singular:", self.singular_extensions),
+        ];
+        for (name, count) in lines.iter() {
+            let percent = if n > 0 { (*count as f64) * 100.0 / (n as f64) } else { 0.0 };
+            println!("# {:15} {:>8} ({:.2} %)", name, count, percent);
+        }
+    }
+}
 
 /// Search the game
 pub trait Search {
     /// Search the number of legal moves at the given depth
     fn perft(&mut self, depth: Depth) -> u64;
 
-    /// Searh the best move at the given depth range
-    fn search(&mut self, depths: Range<Depth>) -> Option<PieceMove>;
+    /// Search the number of legal moves at the given depth after each legal
+    /// move of the current position, using `threads_count` threads
+    fn divide(&mut self, depth: Depth) -> Vec<(PieceMove, u64)>;
 
-    /// Searh the best move from the root position at the given depth range
-    fn search_root(&mut self, depths: Range<Depth>) -> Option<PieceMove>;
+    /// Searh the best move within the given limits
+    fn search(&mut self, limits: SearchLimits) -> Option<PieceMove>;
+
+    /// Search the best lines within the given limits, one for each of the
+    /// `multipv` best moves, sorted from best to worst
+    fn search_lines(&mut self, limits: SearchLimits) -> Vec<SearchLine>;
+
+    /// Get a handle to stop the search of the game from another thread and
+    /// follow its progress
+    fn search_handle(&self) -> SearchHandle;
+
+    /// Searh the best move from the root position within the given limits
+    fn search_root(&mut self, limits: SearchLimits) -> Option<PieceMove>;
 
     /// Searh the best score between alpha and beta from a node position at the given depth
     fn search_node(&mut self, alpha: Score, beta: Score, depth: Depth, ply: usize) -> Score;
@@ -43,14 +269,43 @@ pub trait Search {
 }
 
 trait SearchExt {
-    fn get_pv(&mut self, depth: Depth) -> String;
+    /// Iterative deepening from the root position, returning the best lines
+    /// of the last completed iteration
+    fn search_iterations(&mut self, depths: Range<Depth>, limits: &SearchLimits) -> Vec<SearchLine>;
 
+    /// Get the moves keeping the best result of the tablebases among the
+    /// given moves, or all the legal moves if none is given
+    fn get_tablebase_moves(&mut self, moves: &[PieceMove]) -> Vec<PieceMove>;
+
+    /// Wait for the search to be stopped from another thread when it can't
+    /// return before, during an infinite search or while pondering
     #[cfg(feature = "std")]
-    fn print_debug_init(&self, depth: Depth);
-    #[cfg(feature = "std")]
-    fn print_thinking_init(&self);
+    fn wait_for_stop(&self, limits: &SearchLimits);
+
     #[cfg(feature = "std")]
-    fn print_thinking(&mut self, depth: Depth, score: Score, m: PieceMove);
+    fn print_debug_init(&self, depth: Depth);
+
+    /// Send a line to the observer of a verbose search
+    fn notify_line(&mut self, depth: Depth, score: Score, bound: Bound, pv_index: usize, pv: &[PieceMove]);
+
+    /// Store the moves of a line in the transposition table to follow it
+    /// first in the next iteration
+    fn save_pv(&mut self, line: &SearchLine);
+
+    /// Get the score of a draw for the side to move, taking into account
+    /// the contempt of the root side
+    fn draw_score(&self) -> Score;
+
+    /// Count the legal moves at the given depth, reusing the counts of the
+    /// sub-trees already visited
+    fn perft_node(&mut self, depth: Depth, table: Option<&PerftTable>) -> u64;
+
+    /// Count the legal moves of the current position without playing the
+    /// moves that can't expose the king
+    fn count_legal_moves(&mut self) -> u64;
+
+    /// Get the pieces of the side that are pinned to its king
+    fn pinned_pieces(&self, side: Color) -> Bitboard;
 }
 
 impl Search for Game {
@@ -58,22 +313,96 @@ impl Search for Game {
         if depth == 0 {
             1
         } else {
-            let side = self.side();
-            self.moves.clear();
-            let mut r = 0;
-            while let Some(m) = self.next_move() {
+            self.divide(depth).iter().map(|&(_, n)| n).sum()
+        }
+    }
+
+    fn divide(&mut self, depth: Depth) -> Vec<(PieceMove, u64)> {
+        debug_assert!(depth > 0);
+        let moves = self.get_moves();
+
+        // The counts of the sub-trees are only stored from depth 2, and the
+        // table is kept for the next calls since they are valid in any position
+        let table = if depth > 2 {
+            let table = self.perft_table.get_or_insert_with(|| {
+                Arc::new(PerftTable::with_memory(PERFT_TABLE_SIZE))
+            });
+            Some(table.clone())
+        } else {
+            None
+        };
+
+        let n = if cfg!(feature = "std") { cmp::min(self.threads_count, moves.len()) } else { 0 };
+
+        if n < 2 {
+            return moves.into_iter().map(|m| {
                 self.make_move(m);
-                if !self.is_check(side) {
-                    r += self.perft(depth - 1);
-                }
+                let r = self.perft_node(depth - 1, table.as_deref());
                 self.undo_move(m);
+                (m, r)
+            }).collect();
+        }
+
+        #[cfg(not(feature = "std"))]
+        unreachable!();
+
+        #[cfg(feature = "std")]
+        {
+            // Each thread takes the next root move to count until there is
+            // no more, and they share the counts of the sub-trees
+            let moves = Arc::new(moves);
+            let next_move = Arc::new(AtomicUsize::new(0));
+            let mut workers = Vec::with_capacity(n);
+
+            for i in 0..n {
+                let mut clone = self.clone();
+                let moves = moves.clone();
+                let next_move = next_move.clone();
+                let table = table.clone();
+
+                let builder = thread::Builder::new().
+                    name(format!("perft_{}", i)).
+                    stack_size(4 << 20);
+
+                workers.push(builder.spawn(move || {
+                    let mut res = Vec::new();
+                    loop {
+                        let i = next_move.fetch_add(1, Ordering::Relaxed);
+                        if i >= moves.len() {
+                            break;
+                        }
+                        clone.make_move(moves[i]);
+                        res.push((i, clone.perft_node(depth - 1, table.as_deref())));
+                        clone.undo_move(moves[i]);
+                    }
+                    res
+                }).unwrap());
+            }
+
+            let mut counts = vec![0; moves.len()];
+            for worker in workers {
+                for (i, r) in worker.join().unwrap() {
+                    counts[i] = r;
+                }
             }
-            r
+            moves.iter().copied().zip(counts).collect()
         }
     }
 
-    fn search(&mut self, depths: Range<Depth>) -> Option<PieceMove> {
+    fn search(&mut self, limits: SearchLimits) -> Option<PieceMove> {
+        self.search_lines(limits).first().map(|line| line.best_move)
+    }
+
+    fn search_lines(&mut self, mut limits: SearchLimits) -> Vec<SearchLine> {
         self.nodes_count = 0;
+        self.search_stats = SearchStats::default();
+
+        // Forget everything learned from previous searches that could
+        // change the result of a deterministic search
+        if limits.deterministic {
+            self.tt.clear();
+            self.moves.clear_history();
+        }
         self.tt.reset();
 
         // NOTE: `clear_all()` will zero everything internally, including
@@ -86,16 +415,61 @@ impl Search for Game {
         // case we don't decrement the ply counter that is already at 0.
         self.moves.clear_all();
 
+        // Limit the strength of the engine with a shallow search using a
+        // single thread
+        if self.skill.is_enabled() {
+            limits.depth = Some(cmp::min(limits.max_depth(), self.skill.max_depth()));
+            limits.nodes = Some(cmp::min(limits.nodes.unwrap_or(u64::MAX), self.skill.max_nodes()));
+        }
+
+        // The clock of the game is restored after a deterministic search
+        let game_clock = if limits.deterministic { Some(self.clock.clone()) } else { None };
+
+        if let Some(clock) = &limits.clock {
+            self.clock = clock.clone();
+        }
+        if let Some(time) = limits.movetime {
+            self.clock.set_movetime(time);
+        }
+        if limits.infinite || limits.deterministic {
+            self.clock.set_movetime(u64::MAX);
+        }
+        self.clock.set_nodes_limit(limits.nodes.unwrap_or(u64::MAX));
+        self.clock.detach();
         self.clock.start(self.positions.len());
+        self.clock.attach(&self.search_handle);
+        self.search_handle.start();
+
+        // Restrict the search to the best moves of the tablebases to play
+        // perfect endgames
+        let moves = self.get_tablebase_moves(&limits.searchmoves);
+        if !moves.is_empty() {
+            limits.searchmoves = moves;
+        }
 
-        let n = if cfg!(feature = "std") { self.threads_count } else { 0 };
+        let depths = 1..(limits.max_depth() + 1);
+
+        let n = if cfg!(feature = "std") && !limits.deterministic && !self.skill.is_enabled() {
+            self.threads_count
+        } else {
+            0
+        };
 
         if self.is_debug {
             println!("# using {} threads", n);
         }
 
-        if n == 0 {
-            return self.search_root(depths);
+        if n < 2 {
+            let res = self.search_iterations(depths, &limits);
+
+            #[cfg(feature = "std")]
+            self.wait_for_stop(&limits);
+
+            if let Some(clock) = game_clock {
+                self.clock = clock;
+            }
+            self.search_handle.finish();
+            return res;
         }
 
         #[cfg(not(feature = "std"))]
@@ -103,165 +477,90 @@ impl Search for Game {
 
         #[cfg(feature = "std")]
         {
-            let mut children = Vec::with_capacity(n);
-
-            for i in 0..n {
+            // Lazy SMP
+            //
+            // Helper threads search the same position in parallel and share
+            // their results through the transposition table. Odd threads
+            // start one ply deeper to diversify the search, and all of them
+            // are stopped with the shared clock when the main thread is done.
+            // The best move is then elected by the threads.
+            let mut helpers = Vec::with_capacity(n - 1);
+
+            for i in 1..n {
                 let mut clone = self.clone();
-                if i > 0 {
-                    clone.is_search_verbose = false;
-                    clone.is_debug = false;
-                }
+                clone.is_search_verbose = false;
+                clone.observer = None;
+                clone.search_handle = SearchHandle::new();
+                clone.is_debug = false;
+                clone.is_helper = true;
 
-                let min_depth = depths.start; // TODO: + i as usize;
+                let min_depth = depths.start + (i % 2) as Depth;
                 let max_depth = depths.end;
+                let limits = limits.clone();
 
                 let builder = thread::Builder::new().
                     name(format!("search_{}", i)).
                     stack_size(4 << 20);
 
-                children.push(builder.spawn(move || {
-                    clone.search_root(min_depth..max_depth)
-                }).unwrap());
-            }
-
-            let mut res = Vec::with_capacity(n);
-            for child in children {
-                res.push(child.join().unwrap());
-            }
+                helpers.push(builder.spawn(move || {
+                    let lines = clone.search_iterations(min_depth..max_depth, &limits);
 
-            res[0] // best move found by the first thread
-        }
-    }
-
-    fn search_root(&mut self, depths: Range<Depth>) -> Option<PieceMove> {
-        let hash = self.positions.top().hash;
-        let side = self.side();
-        let ply = 0;
-
-        #[cfg(feature = "std")]
-        if self.is_debug {
-            self.print_debug_init(depths.start);
-        }
-
-        #[cfg(feature = "std")]
-        if self.is_search_verbose {
-            self.print_thinking_init();
-        }
-
-        // Current best move
-        #[allow(unused_assignments)]
-        let mut best_score = 0; // Overwritten before being read in no_std
-        let mut best_move = PieceMove::new_null();
-
-        // Keep track of previous values at shallower depths
-        let mut best_scores = [0; MAX_PLY];
-        let mut best_moves = [PieceMove::new_null(); MAX_PLY];
-
-        debug_assert!(depths.start > 0);
-        for depth in depths {
-            let mut alpha = -INF;
-            let beta = INF;
-
-            self.moves.clear();
-            if !best_move.is_null() {
-                self.moves.add_move(best_move);
-            }
-
-            // Mate pruning
-            if depth > 6 {
-                // Stop the search if the position was mate at the 3 previous
-                // shallower depths.
-                let mut is_mate = true;
-                let inf = INF - (MAX_PLY as Score);
-                for d in 1..4 {
-                    let score = best_scores[(depth - d) as usize];
-                    if -inf < score && score < inf {
-                        is_mate = false;
-                        break;
-                    }
-                }
-                if is_mate {
-                    break;
-                }
+                    // Count the nodes searched since the last poll
+                    clone.clock.add_nodes_count(clone.nodes_count);
+                    lines
+                }).unwrap());
             }
 
-            let mut has_legal_moves = false;
-            while let Some(m) = self.next_move() {
-                if self.clock.poll(self.nodes_count) {
-                    break; // Discard search at this depth if time is out
-                }
-
-                self.make_move(m);
-                let score = -self.search_node(-beta, -alpha, depth - 1, ply + 1);
-                if !self.is_check(side) {
-                    has_legal_moves = true;
-                    self.nodes_count += 1;
-                    if score > alpha {
-                        if self.is_search_verbose && !self.clock.poll(self.nodes_count) {
-                            // TODO: skip the first thousand nodes to gain time?
-
-                            self.tt.set(hash, depth, score, m, Bound::Exact);
+            let mut results = vec![self.search_iterations(depths, &limits)];
 
-                            // Get the PV line from the TT.
-                            #[cfg(feature = "std")]
-                            self.print_thinking(depth, score, m);
-                        }
-                        alpha = score;
-                        best_scores[depth as usize] = score;
-                        best_moves[depth as usize] = m;
-                    }
-                }
-                self.undo_move(m);
-            }
+            self.wait_for_stop(&limits);
 
-            // Save the best move
-            if !best_moves[depth as usize].is_null() && (depth == 1 || !self.clock.poll(self.nodes_count)) {
-                best_move = best_moves[depth as usize];
-                best_score = best_scores[depth as usize];
+            // Stop the helper threads
+            self.clock.stop();
 
-                self.tt.set(hash, depth, best_score, best_move, Bound::Exact);
+            for helper in helpers {
+                results.push(helper.join().unwrap());
             }
 
-            // No need to iterate if there's no legal moves to play
-            if !has_legal_moves {
-                break;
-            }
+            self.search_handle.finish();
+            vote_lines(results)
         }
+    }
 
-        #[cfg(feature = "std")]
-        if self.is_debug {
-            let n = self.nodes_count;
-            let t = self.clock.elapsed_time();
-            let nps = (n as f64) / ((t as f64) / 1000.0);
-            if self.is_search_verbose {
-                println!();
-            }
-            println!("# {:15} {:>8}", "score:", best_score);
-            println!("# {:15} {:>8} ms", "time:", t);
-            println!("# {:15} {:>8} ({:.2e} nps)", "nodes:", n, nps);
-
-            self.tt.print_stats();
-        }
+    fn search_handle(&self) -> SearchHandle {
+        self.search_handle.clone()
+    }
 
-        if best_move.is_null() {
-            None
-        } else {
-            Some(best_move)
-        }
+    fn search_root(&mut self, limits: SearchLimits) -> Option<PieceMove> {
+        let depths = 1..(limits.max_depth() + 1);
+        self.search_iterations(depths, &limits).first().map(|line| line.best_move)
     }
 
     fn search_node(&mut self, mut alpha: Score, mut beta: Score, depth: Depth, ply: usize) -> Score {
+        self.pv_table.clear(ply);
+
         if self.clock.poll(self.nodes_count) {
             return 0;
         }
 
+        self.search_stats.seldepth = cmp::max(self.search_stats.seldepth, ply);
+
         if depth == 0 {
             return self.quiescence(alpha, beta, depth - 1, ply + 1);
         }
 
         // Detect draw by threefold repetitions and fifty-moves rule
         if self.positions.is_draw() {
-            return 0;
+            return self.draw_score();
+        }
+
+        // Endgame Tablebases
+        if let Some(dtm) = self.tablebases.probe(self) {
+            self.search_stats.tbhits += 1;
+            return match dtm {
+                Dtm::Draw => self.draw_score(),
+                _ => dtm.score(ply),
+            };
         }
 
         let hash = self.positions.top().hash;
@@ -269,30 +568,44 @@ impl Search for Game {
         let is_null_move = !self.positions.top().null_move_right;
         let is_pv = alpha != beta - 1;
 
+        // Mate Distance Pruning (MDP)
+        //
+        // The score of the node is bounded by being mated now and mating on
+        // the next ply, so there is nothing to search if a shorter mate was
+        // already found.
+        alpha = cmp::max(alpha, -INF + (ply as Score));
+        beta = cmp::min(beta, INF - (ply as Score) - 1);
+        if alpha >= beta {
+            return alpha;
+        }
+
         let mut best_move = PieceMove::new_null();
         let mut best_score = alpha;
         let old_alpha = alpha; // To test if best score raise initial alpha
 
         // Try to get the best move from transposition_table table
+        let mut tt_entry = None;
         if let Some(t) = self.tt.get(hash) {
+            tt_entry = Some(*t);
+
             if !is_pv && t.depth() >= depth {
                 match t.bound() {
                     Bound::Exact => {
-                        return t.score();
+                        return t.score_at(ply);
                     },
                     Bound::Lower => {
-                        if t.score() > alpha {
-                            alpha = t.score();
+                        if t.score_at(ply) > alpha {
+                            alpha = t.score_at(ply);
                         }
                     },
                     Bound::Upper => {
-                        if t.score() < beta {
-                            beta = t.score();
+                        if t.score_at(ply) < beta {
+                            beta = t.score_at(ply);
                         }
                     }
                 }
                 if alpha >= beta {
-                    return t.score();
+                    return t.score_at(ply);
                 }
             }
 
@@ -301,6 +614,42 @@ impl Search for Game {
 
         let is_in_check = self.is_check(side);
 
+        let inf = INF - (MAX_PLY as Score);
+        let static_pruning_allowed =
+            !is_in_check &&
+            !is_pv &&
+            -inf < alpha && beta < inf;
+
+        if static_pruning_allowed {
+            let static_eval = self.eval();
+            let margins = self.margins;
+
+            // Reverse Futility Pruning (RFP)
+            //
+            // The side to move is so far ahead that the opponent should not
+            // be able to come back in the remaining depth.
+            if depth <= margins.reverse_futility_depth {
+                let margin = margins.reverse_futility * depth as Score;
+                if static_eval - margin >= beta {
+                    return static_eval;
+                }
+            }
+
+            // Razoring
+            //
+            // The side to move is so far behind that only a tactical move
+            // could save it, so the node is verified with a quiescence search.
+            if depth <= margins.razoring_depth {
+                let margin = margins.razoring * depth as Score;
+                if static_eval + margin < alpha {
+                    let score = self.quiescence(alpha, beta, 0, ply);
+                    if depth == 1 || score < alpha {
+                        return score;
+                    }
+                }
+            }
+        }
+
         // Null Move Pruning (NMP)
         let pieces_count = self.bitboard(side).count();
         let pawns_count = self.bitboard(side | PAWN).count();
@@ -340,6 +689,70 @@ impl Search for Game {
             }
         }
 
+        // Singular Extension (SE)
+        //
+        // Extend the best move from the transposition table if all the other
+        // moves fail low with a reduced search under its score.
+        let mut singular_move = PieceMove::new_null();
+        let se_allowed =
+            self.extensions.singular &&
+            !is_null_move &&
+            ply > 1 &&
+            depth >= 8;
+
+        if let Some(t) = tt_entry.filter(|_| se_allowed) {
+            let is_candidate =
+                t.best_move() == best_move &&
+                t.bound() != Bound::Upper &&
+                t.depth() >= depth - 3 &&
+                -inf < t.score_at(ply) && t.score_at(ply) < inf;
+
+            if is_candidate {
+                let singular_beta = t.score_at(ply) - 2 * (depth as Score);
+                singular_move = best_move;
+                self.moves.clear();
+                while let Some(m) = self.next_move() {
+                    if m == best_move {
+                        continue;
+                    }
+                    self.make_move(m);
+                    if self.is_check(side) {
+                        self.undo_move(m);
+                        continue;
+                    }
+                    let score = -self.search_node(-singular_beta, -singular_beta + 1, depth / 2 - 1, ply + 1);
+                    self.undo_move(m);
+
+                    if score >= singular_beta {
+                        singular_move = PieceMove::new_null();
+                        break;
+                    }
+                }
+            }
+        }
+
+        // One Reply Extension
+        //
+        // Count the legal moves when in check to extend forced replies.
+        let mut is_one_reply = false;
+        if self.extensions.one_reply && is_in_check {
+            let mut n = 0;
+            self.moves.clear();
+            while let Some(m) = self.next_move() {
+                self.make_move(m);
+                if !self.is_check(side) {
+                    n += 1;
+                }
+                self.undo_move(m);
+                if n > 1 {
+                    break;
+                }
+            }
+            is_one_reply = n == 1;
+        }
+
+        let prev_move = self.moves.prev_move();
+
         self.moves.clear();
         if !best_move.is_null() {
             self.moves.add_move(best_move);
@@ -360,10 +773,36 @@ impl Search for Game {
             self.nodes_count += 1;
             has_legal_moves = true;
 
+            // Search Extensions
+            //
+            // At most one ply is added to the depth of a move, and only at
+            // the beginning of the tree to keep the search bounded. Recaptures
+            // are only extended in PV nodes where they matter the most.
+            let mut e = 0;
+            if ply < MAX_PLY / 2 {
+                if is_one_reply {
+                    self.search_stats.one_reply_extensions += 1;
+                    e = 1;
+                } else if m == singular_move {
+                    self.search_stats.singular_extensions += 1;
+                    e = 1;
+                } else if self.extensions.check && self.is_check(side ^ 1) {
+                    self.search_stats.check_extensions += 1;
+                    e = 1;
+                } else if self.extensions.recapture && is_pv && m.is_capture() && prev_move.is_capture() && m.to() == prev_move.to() {
+                    self.search_stats.recapture_extensions += 1;
+                    e = 1;
+                }
+            }
+
+            // The depth of the node is kept for the transposition table, the
+            // history, and the pruning decisions
+            let new_depth = depth + e - 1;
+
             let mut score;
             if is_first_move {
                 // Search the first move with the full window
-                score = -self.search_node(-beta, -alpha, depth - 1, ply + 1);
+                score = -self.search_node(-beta, -alpha, new_depth, ply + 1);
 
                 best_score = score;
                 best_move = m;
@@ -380,8 +819,8 @@ impl Search for Game {
                     !m.is_capture() &&
                     !m.is_promotion();
 
-                if fp_allowed && depth < 6 {
-                    let margin = 100 * depth as Score;
+                if fp_allowed && depth <= self.margins.futility_depth {
+                    let margin = self.margins.futility * depth as Score;
                     if eval + margin < alpha {
                         self.undo_move(m);
                         continue;
@@ -404,27 +843,31 @@ impl Search for Game {
                 }
 
                 // Search the other moves with the reduced window
-                score = -self.search_node(-alpha - 1, -alpha, depth - r - 1, ply + 1);
+                score = -self.search_node(-alpha - 1, -alpha, new_depth - r, ply + 1);
 
                 // LMR re-search
                 if r > 0 && score > alpha {
-                    score = -self.search_node(-alpha - 1, -alpha, depth - 1, ply + 1);
+                    score = -self.search_node(-alpha - 1, -alpha, new_depth, ply + 1);
                 }
 
                 // Re-search with the full window
                 if alpha < score && score < beta {
-                    score = -self.search_node(-beta, -alpha, depth - 1, ply + 1);
+                    score = -self.search_node(-beta, -alpha, new_depth, ply + 1);
                 }
             }
 
             self.undo_move(m);
 
             if score > alpha {
+                self.pv_table.update(ply, m);
+
                 if score >= beta {
                     if !m.is_capture() {
                         self.moves.add_killer_move(m);
+                        self.moves.add_history(side, m, depth);
+                        self.moves.add_countermove(m);
                     }
-                    self.tt.set(hash, depth, score, m, Bound::Lower);
+                    self.tt.set(hash, depth, score, m, Bound::Lower, ply);
                     return score;
                 }
 
@@ -439,7 +882,7 @@ impl Search for Game {
             if is_in_check {
                 return -INF + (ply as Score); // Checkmate
             } else {
-                return 0; // Stalemate
+                return self.draw_score(); // Stalemate
             }
         }
 
@@ -449,7 +892,7 @@ impl Search for Game {
             } else {
                 Bound::Upper
             };
-            self.tt.set(hash, depth, best_score, best_move, bound);
+            self.tt.set(hash, depth, best_score, best_move, bound, ply);
         }
 
         alpha
@@ -461,6 +904,8 @@ impl Search for Game {
             return 0;
         }
 
+        self.search_stats.seldepth = cmp::max(self.search_stats.seldepth, ply);
+
         // Static evaluation
         let eval = self.eval();
 
@@ -481,108 +926,404 @@ impl Search for Game {
                 return eval;
             }
 
-            alpha = eval;
-        }
+            alpha = eval;
+        }
+
+        let hash = self.positions.top().hash;
+        let side = self.side();
+        let old_alpha = alpha;
+        let mut best_move = PieceMove::new_null();
+
+        if let Some(t) = self.tt.get(hash) {
+            if t.depth() >= depth { // This node has already been searched
+                match t.bound() {
+                    Bound::Exact => {
+                        return t.score_at(ply);
+                    },
+                    Bound::Lower => {
+                        if t.score_at(ply) > alpha {
+                            alpha = t.score_at(ply);
+                        }
+                    },
+                    Bound::Upper => {
+                        if t.score_at(ply) < beta {
+                            beta = t.score_at(ply);
+                        }
+                    }
+                }
+                if alpha >= beta {
+                    return t.score_at(ply);
+                }
+            }
+
+            best_move = t.best_move();
+        }
+
+        self.moves.clear();
+        if !best_move.is_null() {
+            self.moves.add_move(best_move);
+        }
+        while let Some(m) = self.next_capture() {
+            self.make_move(m);
+
+            if self.is_check(side) {
+                self.undo_move(m);
+                continue;
+            }
+            self.nodes_count += 1;
+
+            let score = -self.quiescence(-beta, -alpha, depth - 1, ply + 1);
+
+            self.undo_move(m);
+
+            if score > alpha {
+                if score >= beta {
+                    self.tt.set(hash, depth, score, m, Bound::Lower, ply);
+                    return score;
+                }
+                alpha = score;
+                best_move = m;
+            }
+        }
+
+        if !best_move.is_null() {
+            let bound = if alpha > old_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            self.tt.set(hash, depth, alpha, best_move, bound, ply);
+        }
+
+        alpha
+    }
+
+    fn is_mate(&mut self) -> bool {
+        let side = self.side();
+        self.moves.clear();
+        while let Some(m) = self.next_move() {
+            self.make_move(m);
+            let is_legal = !self.is_check(side);
+            self.undo_move(m);
+            if is_legal {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn get_moves(&mut self) -> Vec<PieceMove> {
+        let mut res = Vec::new();
+        let side = self.side();
+        self.moves.clear();
+        while let Some(m) = self.next_move() {
+            self.make_move(m);
+            if !self.is_check(side) {
+                res.push(m);
+            }
+            self.undo_move(m);
+        }
+        res
+    }
+}
+
+impl SearchExt for Game {
+    fn search_iterations(&mut self, depths: Range<Depth>, limits: &SearchLimits) -> Vec<SearchLine> {
+        let hash = self.positions.top().hash;
+        let side = self.side();
+        let ply = 0;
+
+        #[cfg(feature = "std")]
+        if self.is_debug {
+            self.print_debug_init(depths.start);
+        }
+
+        if let Some(observer) = self.observer.clone() {
+            observer.on_start(self);
+        }
+
+        // Current best move
+        #[allow(unused_assignments)]
+        let mut best_score = 0; // Overwritten before being read in no_std
+
+        // Best lines of the last completed iteration
+        let mut best_lines: Vec<SearchLine> = Vec::new();
+
+        // Keep track of previous values at shallower depths
+        let mut best_scores = [0; MAX_PLY];
+
+        // Time management
+        let is_forced_move = self.get_moves().len() == 1;
+        let mut best_move_stability = 0;
+        let mut best_move_changes = 0;
+
+        let n = cmp::max(self.multipv, 1);
+
+        // Search more lines to choose a weaker move from when the strength
+        // of the engine is limited
+        let n = if self.skill.is_enabled() { cmp::max(n, self.skill.multipv()) } else { n };
+
+        debug_assert!(depths.start > 0);
+        for depth in depths.clone() {
+            let iteration_started_at = self.clock.elapsed_time();
+
+            // Mate pruning
+            if depth > 6 {
+                // Stop the search if the position was mate at the 3 previous
+                // shallower depths.
+                let mut is_mate = true;
+                let inf = INF - (MAX_PLY as Score);
+                for d in 1..4 {
+                    let score = best_scores[(depth - d) as usize];
+                    if -inf < score && score < inf {
+                        is_mate = false;
+                        break;
+                    }
+                }
+                if is_mate {
+                    break;
+                }
+            }
+
+            // MultiPV
+            //
+            // Search the root moves once for each line, excluding the first
+            // moves of the lines already found at this depth.
+            let mut lines: Vec<SearchLine> = Vec::with_capacity(n);
+            let mut has_legal_moves = false;
+            for pv_index in 0..n {
+                let previous_line = best_lines.get(pv_index);
+
+                // Aspiration window
+                //
+                // Search with a narrow window centered on the score of the
+                // previous iteration, and widen it each time the search fails
+                // high or low.
+                let mut delta = ASPIRATION_WINDOW;
+                let mut alpha = -INF;
+                let mut beta = INF;
+                let inf = INF - (MAX_PLY as Score);
+                if let Some(line) = previous_line {
+                    let score = line.score;
+                    if depth >= ASPIRATION_DEPTH && -inf < score && score < inf {
+                        alpha = cmp::max(score - delta, -INF);
+                        beta = cmp::min(score + delta, INF);
+                    }
+                }
+
+                let mut line_score = -INF;
+                let mut line_move = PieceMove::new_null();
+                let mut line_pv = Vec::new();
+                loop {
+                    let old_alpha = alpha;
+                    let mut is_fail_high = false;
+
+                    // Try the move that failed high first when re-searching
+                    let first_move = match previous_line {
+                        Some(line) if line_move.is_null() => line.best_move,
+                        _ => line_move,
+                    };
+
+                    // Skip the moves of the lines already found and the moves
+                    // outside of the search restrictions
+                    let is_excluded = |m: PieceMove| {
+                        lines.iter().any(|line| line.best_move == m) ||
+                        (!limits.searchmoves.is_empty() && !limits.searchmoves.contains(&m))
+                    };
+
+                    self.moves.clear();
+                    if !first_move.is_null() && !is_excluded(first_move) {
+                        self.moves.add_move(first_move);
+                    }
+
+                    let mut move_number = 0;
+                    while let Some(m) = self.next_move() {
+                        // Helper threads always complete their first depth
+                        let is_interruptible = !self.is_helper || depth > depths.start;
+                        if is_interruptible && self.clock.poll(self.nodes_count) {
+                            break; // Discard search at this depth if time is out
+                        }
+
+                        if is_excluded(m) {
+                            continue;
+                        }
 
-        let hash = self.positions.top().hash;
-        let side = self.side();
-        let old_alpha = alpha;
-        let mut best_move = PieceMove::new_null();
+                        self.make_move(m);
+                        if self.is_check(side) {
+                            self.undo_move(m);
+                            continue;
+                        }
 
-        if let Some(t) = self.tt.get(hash) {
-            if t.depth() >= depth { // This node has already been searched
-                match t.bound() {
-                    Bound::Exact => {
-                        return t.score();
-                    },
-                    Bound::Lower => {
-                        if t.score() > alpha {
-                            alpha = t.score();
+                        move_number += 1;
+                        if let Some(observer) = self.observer.clone() {
+                            self.undo_move(m);
+                            observer.on_current_move(self, depth, m, move_number);
+                            self.make_move(m);
                         }
-                    },
-                    Bound::Upper => {
-                        if t.score() < beta {
-                            beta = t.score();
+
+                        let score = -self.search_node(-beta, -alpha, depth - 1, ply + 1);
+                        self.undo_move(m);
+
+                        has_legal_moves = true;
+                        self.nodes_count += 1;
+                        if score > alpha {
+                            let bound = if score >= beta { Bound::Lower } else { Bound::Exact };
+
+                            self.pv_table.update(ply, m);
+                            line_pv = self.pv_table.get(ply).to_vec();
+                            if !self.clock.poll(self.nodes_count) {
+                                self.notify_line(depth, score, bound, pv_index, &line_pv);
+                            }
+                            line_score = score;
+                            line_move = m;
+
+                            if score >= beta {
+                                is_fail_high = true;
+                                break;
+                            }
+                            alpha = score;
                         }
                     }
+
+                    if !has_legal_moves || self.clock.poll(self.nodes_count) {
+                        break;
+                    }
+
+                    if is_fail_high {
+                        beta = cmp::min(beta.saturating_add(delta), INF);
+                    } else if alpha == old_alpha && alpha > -INF {
+                        let pv = match previous_line {
+                            Some(line) if line.best_move == first_move => &line.pv[..],
+                            _ if !first_move.is_null() => &[first_move][..],
+                            _ => &[],
+                        };
+                        self.notify_line(depth, alpha, Bound::Upper, pv_index, pv);
+                        alpha = cmp::max(alpha.saturating_sub(delta), -INF);
+                    } else {
+                        break;
+                    }
+                    delta = delta.saturating_mul(2);
                 }
-                if alpha >= beta {
-                    return t.score();
+
+                // No more moves to search or no more time to search them
+                if line_move.is_null() || (depth > 1 && self.clock.poll(self.nodes_count)) {
+                    break;
                 }
+
+                self.tt.set(hash, depth, line_score, line_move, Bound::Exact, ply);
+
+                lines.push(SearchLine { best_move: line_move, score: line_score, depth, pv: line_pv });
             }
 
-            best_move = t.best_move();
-        }
+            // A line can score higher than the lines found before it when
+            // it's searched deeper by an extension, so they are sorted again.
+            lines.sort_by_key(|line| cmp::Reverse(line.score));
+
+            // Save the best lines
+            let mut is_iteration_completed = false;
+            if !lines.is_empty() && (depth == 1 || !self.clock.poll(self.nodes_count)) {
+                // Count the iterations with the same best move and how often
+                // it changed recently
+                best_move_changes /= 2;
+                match best_lines.first() {
+                    Some(line) if line.best_move == lines[0].best_move => {
+                        best_move_stability += 1;
+                    },
+                    Some(_) => {
+                        best_move_stability = 0;
+                        best_move_changes += 100;
+                    },
+                    None => {}
+                }
 
-        self.moves.clear();
-        if !best_move.is_null() {
-            self.moves.add_move(best_move);
-        }
-        while let Some(m) = self.next_capture() {
-            self.make_move(m);
+                best_score = lines[0].score;
+                best_scores[depth as usize] = best_score;
 
-            if self.is_check(side) {
-                self.undo_move(m);
-                continue;
+                self.save_pv(&lines[0]);
+                self.search_handle.update(lines[0].best_move, depth);
+
+                best_lines = lines;
+                is_iteration_completed = true;
             }
-            self.nodes_count += 1;
 
-            let score = -self.quiescence(-beta, -alpha, depth - 1, ply + 1);
+            // Time management
+            //
+            // Spend more time on the move when the best move changed or the
+            // score dropped in the last iterations, less time when the best
+            // move is stable or forced, and don't start an iteration that is
+            // not expected to finish in time.
+            if is_iteration_completed && depth >= TIME_MANAGEMENT_DEPTH {
+                // The score of the previous depth is unknown to the helper
+                // threads starting deeper
+                let score_drop = best_scores[(depth - 1) as usize] as i32 - best_score as i32;
+                let mut time_factor = 100 + best_move_changes;
+                time_factor -= 10 * cmp::min(best_move_stability, 5);
+                if score_drop > 20 && depth > depths.start {
+                    time_factor += cmp::min(score_drop, 100) as u64;
+                }
+                if is_forced_move {
+                    time_factor = 10;
+                }
+                self.clock.set_time_factor(time_factor);
 
-            self.undo_move(m);
+                // The clock is restarted while pondering
+                let iteration_time = self.clock.elapsed_time().saturating_sub(iteration_started_at);
+                if !self.clock.can_start_iteration(iteration_time) {
+                    break;
+                }
+            }
 
-            if score > alpha {
-                if score >= beta {
-                    self.tt.set(hash, depth, score, m, Bound::Lower);
-                    return score;
+            // Stop the search when a short enough mate has been found
+            if let Some(score) = limits.mate_score() {
+                if best_score >= score {
+                    break;
                 }
-                alpha = score;
-                best_move = m;
+            }
+
+            // No need to iterate if there's no legal moves to play
+            if !has_legal_moves {
+                break;
             }
         }
 
-        if !best_move.is_null() {
-            let bound = if alpha > old_alpha {
-                Bound::Exact
-            } else {
-                Bound::Upper
-            };
-            self.tt.set(hash, depth, alpha, best_move, bound);
+        // Play a weaker line when the strength of the engine is limited
+        if self.skill.is_enabled() && !best_lines.is_empty() {
+            let i = self.skill.pick_line(&best_lines);
+            let line = best_lines.remove(i);
+            best_lines.insert(0, line);
+            best_lines.truncate(cmp::max(self.multipv, 1));
         }
 
-        alpha
-    }
+        if let Some(observer) = self.observer.clone() {
+            observer.on_finish(self, &best_lines);
+        }
 
-    fn is_mate(&mut self) -> bool {
-        let side = self.side();
-        self.moves.clear();
-        while let Some(m) = self.next_move() {
-            self.make_move(m);
-            let is_legal = !self.is_check(side);
-            self.undo_move(m);
-            if is_legal {
-                return false;
+        #[cfg(feature = "std")]
+        if self.is_debug {
+            let n = self.clock.total_nodes_count(self.nodes_count);
+            let t = self.clock.elapsed_time();
+            let nps = (n as f64) / ((t as f64) / 1000.0);
+            if self.is_search_verbose {
+                println!();
             }
+            println!("# {:15} {:>8}", "score:", best_score);
+            println!("# {:15} {:>8} ms", "time:", t);
+            println!("# {:15} {:>8} ({:.2e} nps)", "nodes:", n, nps);
+
+            self.search_stats.print();
+            self.tt.print_stats();
         }
-        true
+
+        best_lines
     }
 
-    fn get_moves(&mut self) -> Vec<PieceMove> {
-        let mut res = Vec::new();
-        let side = self.side();
-        self.moves.clear();
-        while let Some(m) = self.next_move() {
-            self.make_move(m);
-            if !self.is_check(side) {
-                res.push(m);
-            }
-            self.undo_move(m);
+    #[cfg(feature = "std")]
+    fn wait_for_stop(&self, limits: &SearchLimits) {
+        while !self.clock.is_finished() && (limits.infinite || self.clock.is_pondering()) {
+            thread::sleep(Duration::from_millis(10));
         }
-        res
     }
-}
 
-impl SearchExt for Game {
     #[cfg(feature = "std")]
     fn print_debug_init(&self, depth: Depth) {
         println!("# FEN {}", self.to_fen());
@@ -591,109 +1332,159 @@ impl SearchExt for Game {
         println!();
     }
 
-    #[cfg(feature = "std")]
-    fn print_thinking_init(&self) {
-        if self.protocol != Protocol::UCI {
-            println!("  {:>3}  {:>5}  {:>6}  {:>9}  {}", "ply", "score", "time", "nodes", "pv");
+    fn perft_node(&mut self, depth: Depth, table: Option<&PerftTable>) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        if depth == 1 {
+            return self.count_legal_moves(); // Bulk counting
         }
-    }
-
-    #[cfg(feature = "std")]
-    fn print_thinking(&mut self, depth: Depth, score: Score, m: PieceMove) {
-        self.undo_move(m);
-
-        let time = self.clock.elapsed_time();
-        let nodes = self.nodes_count;
-        let mut pv = self.get_pv(depth);
-
-        match self.protocol {
-            Protocol::UCI => {
-                println!("info depth {} score cp {} time {} nodes {} pv {}", depth, score, time, nodes, pv);
-            },
-            Protocol::XBoard | Protocol::CLI => {
-                if self.side() == BLACK {
-                    let fm = self.positions.fullmoves();
-                    pv = format!("{}. ... {}", fm, pv);
-                }
 
-                // Split PV over multiple lines of 80 chars max in CLI mode
-                if self.protocol == Protocol::CLI {
-                    let mut width = 34;
-                    let mut lines = Vec::new();
-                    let mut line = Vec::new();
-                    for chunk in pv.trim().split(' ').collect::<Vec<&str>>().chunks(3) {
-                        let s = chunk.join(" ");
-                        if width + s.len() >= 80 {
-                            width = 34;
-                            lines.push(line.join(" "));
-                            line.clear();
-                        }
-                        width += s.len() + 1;
-                        line.push(s);
-                    }
-                    if line.len() > 0 {
-                        lines.push(line.join(" "));
-                    }
-                    pv = lines.join(&format!("{:<34}", "\n"));
-                }
+        let table = table.expect("perft table from depth 2");
+        let hash = self.positions.top().hash;
+        if let Some(r) = table.get(hash, depth) {
+            return r;
+        }
 
-                println!("  {:>3}  {:>5}  {:>6}  {:>9}  {}", depth, score, time / 10, nodes, pv);
+        let side = self.side();
+        self.moves.clear();
+        let mut r = 0;
+        while let Some(m) = self.next_move() {
+            self.make_move(m);
+            if !self.is_check(side) {
+                r += self.perft_node(depth - 1, Some(table));
             }
+            self.undo_move(m);
         }
 
-        self.make_move(m);
+        table.set(hash, depth, r);
+        r
     }
 
-    fn get_pv(&mut self, depth: Depth) -> String {
-        #[cfg(feature = "std")]
-        let is_san_format = self.protocol != Protocol::UCI;
-        #[cfg(not(feature = "std"))]
-        let is_san_format = false;
+    fn count_legal_moves(&mut self) -> u64 {
+        let side = self.side();
+        let king = self.bitboard(side | KING).scan() as Square;
+        let is_in_check = self.is_check(side);
+        let pinned = if is_in_check { 0 } else { self.pinned_pieces(side) };
 
-        if depth == 0 {
-            return String::new();
+        self.moves.clear();
+        let mut r = 0;
+        while let Some(m) = self.next_move() {
+            // Only the moves of the king and of pinned pieces, en passant
+            // captures, and evasions need to be played to be verified
+            if is_in_check || m.from() == king || pinned.get(m.from()) || m.is_en_passant() {
+                self.make_move(m);
+                if !self.is_check(side) {
+                    r += 1;
+                }
+                self.undo_move(m);
+            } else {
+                r += 1;
+            }
         }
+        r
+    }
 
-        let mut res = vec![];
-        let mut m = PieceMove::new_null();
-
-        let side = self.side();
-        let hash = self.positions.top().hash;
-        if let Some(t) = self.tt.get(hash) {
-            m = t.best_move();
-
-            if is_san_format && side == WHITE {
-                let fm = self.positions.fullmoves();
-                res.push(format!("{}.", fm));
+    fn pinned_pieces(&self, side: Color) -> Bitboard {
+        let occupied = self.bitboard(WHITE) | self.bitboard(BLACK);
+        let king = self.bitboard(side | KING).scan() as Square;
+        let queens = self.bitboard(side ^ 1 | QUEEN);
+        let rooks = self.bitboard(side ^ 1 | ROOK) | queens;
+        let bishops = self.bitboard(side ^ 1 | BISHOP) | queens;
+
+        // A piece between the king and a slider is pinned if removing it
+        // would put the king in check
+        let mut pinned = 0;
+        let mut blockers = (rook_attacks(king, occupied) | bishop_attacks(king, occupied)) & self.bitboard(side);
+        while let Some(sq) = blockers.next() {
+            let mut occupied = occupied;
+            occupied.reset(sq);
+            if rook_attacks(king, occupied) & rooks != 0 || bishop_attacks(king, occupied) & bishops != 0 {
+                pinned.set(sq);
             }
+        }
+        pinned
+    }
 
-            // TODO: put the rest of the code here (if the compiler allow it)
+    fn draw_score(&self) -> Score {
+        // The root side is to move at even plies
+        if self.moves.ply() & 1 == 0 {
+            -self.contempt
+        } else {
+            self.contempt
         }
+    }
 
-        if !m.is_null() {
-            let cur = if is_san_format {
-                self.move_to_san(m)
-            } else {
-                m.to_lan()
+    fn notify_line(&mut self, depth: Depth, score: Score, bound: Bound, pv_index: usize, pv: &[PieceMove]) {
+        // Building the info is only worth it when the search is verbose
+        if !self.is_search_verbose {
+            return;
+        }
+        if let Some(observer) = self.observer.clone() {
+            let info = SearchInfo {
+                depth,
+                seldepth: self.search_stats.seldepth,
+                multipv: pv_index + 1,
+                score,
+                bound,
+                nodes: self.clock.total_nodes_count(self.nodes_count),
+                time: self.clock.elapsed_time(),
+                hashfull: self.tt.hashfull(),
+                tbhits: self.search_stats.tbhits,
+                pv: pv.to_vec(),
             };
+            observer.on_line(self, &info);
+        }
+    }
+
+    fn save_pv(&mut self, line: &SearchLine) {
+        // Only the root has an exact score at the depth of the line, the
+        // other nodes may have been searched at another depth or cut by the
+        // transposition table, so they keep the depth, score, and bound of
+        // their entry and only get the move of the line.
+        let mut played = Vec::with_capacity(line.pv.len());
+        for (ply, &m) in line.pv.iter().enumerate() {
+            let hash = self.positions.top().hash;
+            if ply == 0 {
+                self.tt.set(hash, line.depth, line.score, m, Bound::Exact, ply);
+            } else if let Some(t) = self.tt.get(hash).copied() {
+                self.tt.set(hash, t.depth(), t.score_at(ply), m, t.bound(), ply);
+            }
             self.make_move(m);
+            played.push(m);
+        }
+        for &m in played.iter().rev() {
+            self.undo_move(m);
+        }
+    }
 
-            let pv = &self.get_pv(depth - 1);
-            let sep = if is_san_format && self.is_check(side ^ 1) {
-                if pv == "#" { "" } else { "+ " }
-            } else {
-                " "
-            };
-            res.push(format!("{}{}{}", cur, sep, pv));
+    fn get_tablebase_moves(&mut self, moves: &[PieceMove]) -> Vec<PieceMove> {
+        let mut res = Vec::new();
+        if self.tablebases.probe(self).is_none() {
+            return res;
+        }
 
+        let mut best = None;
+        for m in self.get_moves() {
+            if !moves.is_empty() && !moves.contains(&m) {
+                continue;
+            }
+            self.make_move(m);
+            let dtm = self.tablebases.probe(self).map(|dtm| dtm.parent());
             self.undo_move(m);
-        } else if self.is_check(side) {
-            if is_san_format {
-                res.push("#".into());
+
+            if dtm.is_none() {
+                return Vec::new();
+            }
+            if dtm > best {
+                best = dtm;
+                res.clear();
+            }
+            if dtm == best {
+                res.push(m);
             }
         }
-
-        res.join(" ")
+        res
     }
 }
 
@@ -713,7 +1504,9 @@ mod tests {
     use crate::piece_move::PieceMove;
     use crate::piece_move_generator::PieceMoveGenerator;
     use crate::piece_move_notation::PieceMoveNotation;
-    use crate::search::Search;
+    use crate::search::{Search, SearchLine, mate_in};
+    use crate::search_limits::SearchLimits;
+    use crate::skill::Skill;
 
     #[test]
     fn test_perft() {
@@ -766,6 +1559,20 @@ mod tests {
         assert_eq!(game.perft(3), 53392);
     }
 
+    #[test]
+    fn test_divide() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut game = Game::from_fen(fen).unwrap();
+        let res = game.divide(3);
+        assert_eq!(res.len(), 48);
+        assert_eq!(res.iter().map(|&(_, n)| n).sum::<u64>(), 97862);
+
+        // Count the nodes of the root moves in parallel
+        game.threads_count = 4;
+        assert_eq!(game.divide(3), res);
+        assert_eq!(game.perft(4), 4085603);
+    }
+
     #[test]
     fn test_search_node() {
         let fen = "4k3/8/4q3/8/8/4Q3/8/4K3 w - - 0 1";
@@ -853,23 +1660,343 @@ mod tests {
         let fen = "2k4r/ppp3pp/8/2b2p1P/PPP2p2/N4P2/3r2K1/1q5R w - - 4 29";
         let best_move = PieceMove::new(G2, H3, QUIET_MOVE);
         let mut game = Game::from_fen(fen).unwrap();
-        game.clock = Clock::new(1, 5 * 1000); // 5 seconds
-        let m = game.search(1..10).unwrap();
+        let limits = SearchLimits {
+            depth: Some(9),
+            clock: Some(Clock::new(1, 5 * 1000)), // 5 seconds
+            ..SearchLimits::new()
+        };
+        let m = game.search(limits).unwrap();
         assert_eq!(m.to_string(), best_move.to_string());
 
 
         let fen = "r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - -";
         let best_move = PieceMove::new(H6, H7, CAPTURE);
         let mut game = Game::from_fen(fen).unwrap();
-        game.clock = Clock::new(1, 5 * 1000); // 5 seconds
-        let m = game.search(1..10).unwrap();
+        let limits = SearchLimits {
+            depth: Some(9),
+            clock: Some(Clock::new(1, 5 * 1000)), // 5 seconds
+            ..SearchLimits::new()
+        };
+        let m = game.search(limits).unwrap();
         assert_eq!(m.to_string(), best_move.to_string());
 
 
         let fen = "1n6/2rp3p/5Bpk/2p1P3/p1P2P2/5K2/PPB3P1/R6R b - - 0 1";
         let mut game = Game::from_fen(fen).unwrap();
-        game.clock = Clock::new(1, 1 * 1000); // 1 seconds
-        assert_eq!(game.search(1..10), None);
+        let limits = SearchLimits {
+            depth: Some(9),
+            clock: Some(Clock::new(1, 1 * 1000)), // 1 seconds
+            ..SearchLimits::new()
+        };
+        assert_eq!(game.search(limits), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_search_in_threads() {
+        let fen = "r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - -";
+        let best_move = PieceMove::new(H6, H7, CAPTURE);
+        let mut game = Game::from_fen(fen).unwrap();
+        game.threads_count = 4;
+
+        // The time limit is far enough to always reach the depth limit
+        let limits = SearchLimits {
+            depth: Some(6),
+            clock: Some(Clock::new(1, 60 * 1000)), // 60 seconds
+            ..SearchLimits::new()
+        };
+        let m = game.search(limits).unwrap();
+        assert_eq!(m.to_string(), best_move.to_string());
+
+        // The helper threads always complete their first depth, and their
+        // nodes are added to the total
+        assert!(game.clock.total_nodes_count(game.nodes_count) > game.nodes_count);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_vote_lines() {
+        use crate::search::vote_lines;
+
+        let e2e4 = PieceMove::new(E2, E4, DOUBLE_PAWN_PUSH);
+        let d2d4 = PieceMove::new(D2, D4, DOUBLE_PAWN_PUSH);
+        let g1f3 = PieceMove::new(G1, F3, QUIET_MOVE);
+        let line = |m: PieceMove, score: Score, depth: Depth| {
+            vec![SearchLine { best_move: m, score, depth, pv: vec![m] }]
+        };
+
+        // Two votes for a move are worth more than a single better one
+        let results = vec![
+            line(d2d4, 40, 10),
+            line(e2e4, 30, 10),
+            line(e2e4, 35, 10),
+            line(g1f3, 90, 9), // Shallower threads don't vote
+        ];
+        assert_eq!(vote_lines(results), line(e2e4, 35, 10));
+
+        // A much better score outweighs the number of votes
+        let results = vec![
+            line(d2d4, 20, 10),
+            line(d2d4, 20, 10),
+            line(e2e4, 60, 10),
+        ];
+        assert_eq!(vote_lines(results), line(e2e4, 60, 10));
+
+        assert_eq!(vote_lines(vec![vec![], line(d2d4, 0, 1)]), line(d2d4, 0, 1));
+        assert_eq!(vote_lines(vec![vec![], vec![]]), vec![]);
+    }
+
+    #[test]
+    fn test_search_lines() {
+        let fen = "r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - -";
+        let best_move = PieceMove::new(H6, H7, CAPTURE);
+        let mut game = Game::from_fen(fen).unwrap();
+        game.multipv = 3;
+        let limits = SearchLimits {
+            depth: Some(7),
+            clock: Some(Clock::new(1, 5 * 1000)), // 5 seconds
+            ..SearchLimits::new()
+        };
+        let lines = game.search_lines(limits);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].best_move.to_string(), best_move.to_string());
+        for i in 0..3 {
+            assert_eq!(lines[i].pv[0], lines[i].best_move);
+            for j in (i + 1)..3 {
+                assert!(lines[i].score >= lines[j].score);
+                assert_ne!(lines[i].best_move, lines[j].best_move);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_limits() {
+        let fen = "r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - -";
+        let mut game = Game::from_fen(fen).unwrap();
+
+        // Restrict the search to a move that is not the best
+        let m = game.move_from_lan("d3e4");
+        let limits = SearchLimits {
+            depth: Some(4),
+            searchmoves: vec![m],
+            ..SearchLimits::new()
+        };
+        assert_eq!(game.search(limits), Some(m));
+
+        // Stop the search after a number of nodes
+        let limits = SearchLimits {
+            nodes: Some(10000),
+            ..SearchLimits::new()
+        };
+        game.search(limits);
+        assert!(game.clock.total_nodes_count(game.nodes_count) < 20000);
+
+        // Stop the search when a mate is found
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+        let mut game = Game::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            mate: Some(1),
+            movetime: Some(5000),
+            ..SearchLimits::new()
+        };
+        let m = game.search(limits).unwrap();
+        assert_eq!(m.to_lan(), "d1d8");
+        assert!(game.clock.elapsed_time() < 1000);
+    }
+
+    #[test]
+    fn test_search_pv() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let mut game = Game::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(6),
+            clock: Some(Clock::new(1, 5 * 1000)), // 5 seconds
+            ..SearchLimits::new()
+        };
+        let lines = game.search_lines(limits);
+        let pv = &lines[0].pv;
+        assert_eq!(pv[0], lines[0].best_move);
+        assert!(pv.len() >= 4);
+
+        // The PV is made of legal moves and is stored in the TT up to the
+        // depth of the search
+        for (i, &m) in pv.iter().enumerate() {
+            assert!(game.get_moves().contains(&m));
+            if i < lines[0].depth as usize {
+                let hash = game.positions.top().hash;
+                assert_eq!(game.tt.get(hash).map(|t| t.best_move()), Some(m));
+            }
+            game.make_move(m);
+        }
+    }
+
+    #[test]
+    fn test_contempt() {
+        // Every move is a draw by the fifty-move rule
+        let fen = "4k3/8/8/8/8/8/8/4K2R w - - 99 80";
+        let mut game = Game::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(3),
+            clock: Some(Clock::new(1, 5 * 1000)), // 5 seconds
+            ..SearchLimits::new()
+        };
+        for contempt in [0, 20, -20] {
+            game.contempt = contempt;
+            let lines = game.search_lines(limits.clone());
+            assert_eq!(lines[0].score, -contempt);
+        }
+    }
+
+    #[test]
+    fn test_search_skill() {
+        let fen = "r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - -";
+        let mut game = Game::from_fen(fen).unwrap();
+        game.skill = Skill::new(0);
+        let limits = SearchLimits {
+            clock: Some(Clock::new(1, 5 * 1000)), // 5 seconds
+            ..SearchLimits::new()
+        };
+        let lines = game.search_lines(limits);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].depth, game.skill.max_depth());
+        assert!(game.nodes_count <= game.skill.max_nodes() + game.clock.polling_nodes_count);
+    }
+
+    #[test]
+    fn test_search_deterministic() {
+        let fen = "r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - -";
+        let mut game = Game::from_fen(fen).unwrap();
+        game.threads_count = 4;
+        let limits = SearchLimits {
+            nodes: Some(50000),
+            deterministic: true,
+            ..SearchLimits::new()
+        };
+
+        // The same search gives the same result even after another search
+        // has filled the tables of the game
+        let m1 = game.search(limits.clone());
+        let n1 = game.nodes_count;
+        game.search(SearchLimits { depth: Some(5), ..SearchLimits::new() });
+        let m2 = game.search(limits.clone());
+        let n2 = game.nodes_count;
+        assert!(m1.is_some());
+        assert_eq!(m1, m2);
+        assert_eq!(n1, n2);
+        assert!(n1 <= 50000 + game.clock.polling_nodes_count);
+    }
+
+    #[test]
+    fn test_search_extensions() {
+        // A position without a forced mate to search every iteration
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let mut game = Game::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(4),
+            clock: Some(Clock::new(1, 10 * 1000)),
+            ..SearchLimits::new()
+        };
+
+        game.search(limits.clone());
+        assert!(game.search_stats.check_extensions > 0);
+        assert!(game.search_stats.extensions() > 0);
+
+        game.extensions.check = false;
+        game.extensions.one_reply = false;
+        game.extensions.recapture = false;
+        game.extensions.singular = false;
+        game.search(limits);
+        assert_eq!(game.search_stats.extensions(), 0);
+    }
+
+    #[test]
+    fn test_static_pruning() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let limits = SearchLimits {
+            depth: Some(6),
+            clock: Some(Clock::new(1, 10 * 1000)),
+            ..SearchLimits::new()
+        };
+
+        let mut game = Game::from_fen(fen).unwrap();
+        game.search(limits.clone());
+        let nodes_with_pruning = game.nodes_count;
+
+        let mut game = Game::from_fen(fen).unwrap();
+        game.margins.reverse_futility_depth = 0;
+        game.margins.razoring_depth = 0;
+        game.search(limits);
+        let nodes_without_pruning = game.nodes_count;
+
+        assert!(nodes_with_pruning < nodes_without_pruning);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_search_ponder() {
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        let mut game = Game::from_fen(DEFAULT_FEN).unwrap();
+        game.clock = Clock::new(1, 100); // 0.1 second
+        game.clock.ponder();
+
+        // Switch to normal time management after 0.5 second
+        let mut clock = game.clock.clone();
+        let ponderer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            clock.ponderhit();
+        });
+
+        let started_at = Instant::now();
+        assert!(game.search(SearchLimits::new()).is_some());
+        assert!(started_at.elapsed() >= Duration::from_millis(500));
+        assert!(started_at.elapsed() < Duration::from_millis(2000));
+        ponderer.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_search_handle() {
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        let mut game = Game::from_fen(DEFAULT_FEN).unwrap();
+        let handle = game.search_handle();
+        assert!(!handle.is_running());
+        assert_eq!(handle.best_move(), None);
+
+        let started_at = Instant::now();
+        let searcher = thread::spawn(move || {
+            game.search(SearchLimits { infinite: true, ..SearchLimits::new() })
+        });
+
+        // Follow the progress of the search from another thread
+        thread::sleep(Duration::from_millis(200));
+        assert!(handle.is_running());
+        assert!(handle.depth() > 0);
+        assert!(handle.nodes_count() > 0);
+        let best_move = handle.best_move();
+        assert!(best_move.is_some());
+
+        handle.stop();
+        let m = searcher.join().unwrap();
+        assert!(started_at.elapsed() < Duration::from_millis(2000));
+        assert!(m.is_some());
+        assert!(!handle.is_running());
+        assert!(!handle.is_stopped());
+
+        // A stop requested before the search is kept until its start
+        let mut game = Game::from_fen(DEFAULT_FEN).unwrap();
+        game.search_handle().stop();
+        let limits = SearchLimits { infinite: true, ..SearchLimits::new() };
+        assert_eq!(game.search(limits), None);
+
+        // The stop request can be cleared before the search
+        game.search_handle().stop();
+        game.search_handle().clear();
+        let limits = SearchLimits { depth: Some(2), ..SearchLimits::new() };
+        assert!(game.search(limits).is_some());
     }
 
     #[test]
@@ -912,8 +2039,12 @@ mod tests {
         game.make_move(m2);
         game.make_move(m3);
         game.make_move(m4);
-        game.clock = Clock::new(1, 1000); // 1 second
-        let m = game.search(1..10).unwrap();
+        let limits = SearchLimits {
+            depth: Some(9),
+            clock: Some(Clock::new(1, 1000)), // 1 second
+            ..SearchLimits::new()
+        };
+        let m = game.search(limits).unwrap();
         assert!(m != m1);
     }
 
@@ -922,8 +2053,12 @@ mod tests {
         // Zugzwang #1
         let fen = "1q1k4/2Rr4/8/2Q3K1/8/8/8/8 w - - 0 1";
         let mut game = Game::from_fen(fen).unwrap();
-        game.clock = Clock::new(1, 5000); // 1 second
-        let m = game.search(1..100).unwrap();
+        let limits = SearchLimits {
+            depth: Some(99),
+            clock: Some(Clock::new(1, 5000)), // 1 second
+            ..SearchLimits::new()
+        };
+        let m = game.search(limits).unwrap();
         assert_eq!(m, PieceMove::new(G5, H6, QUIET_MOVE));
 
         // Zugzwang #2
@@ -931,12 +2066,37 @@ mod tests {
         //FIXME: this position takes too long at the moment
         let fen = "8/8/p1p5/1p5p/1P5p/8/PPP2K1p/4R1rk w - - 0 1";
         let mut game = Game::from_fen(fen).unwrap();
-        game.clock = Clock::new(1, 1000); // 1 second
-        let m = game.search(1..100).unwrap();
+        let limits = SearchLimits {
+            depth: Some(99),
+            clock: Some(Clock::new(1, 1000)), // 1 second
+            ..SearchLimits::new()
+        };
+        let m = game.search(limits).unwrap();
         assert_eq!(m, PieceMove::new(E1, F1, QUIET_MOVE));
         */
     }
 
+    #[test]
+    fn test_mate_in() {
+        assert_eq!(mate_in(INF - 1), Some(1));
+        assert_eq!(mate_in(INF - 3), Some(2));
+        assert_eq!(mate_in(-INF + 2), Some(-1));
+        assert_eq!(mate_in(-INF + 4), Some(-2));
+        assert_eq!(mate_in(250), None);
+        assert_eq!(mate_in(-250), None);
+
+        // Mate in 2 found with TT scores of nodes at different plies
+        let fen = "r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - -";
+        let mut game = Game::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(8),
+            clock: Some(Clock::new(1, 5 * 1000)),
+            ..SearchLimits::new()
+        };
+        let lines = game.search_lines(limits);
+        assert_eq!(mate_in(lines[0].score), Some(2));
+    }
+
     #[test]
     fn test_is_mate() {
         let fen = "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq g3 0 2";
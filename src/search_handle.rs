@@ -0,0 +1,86 @@
+use std::prelude::v1::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI8, AtomicU16, AtomicU64, Ordering};
+
+use crate::square::*;
+use crate::common::*;
+use crate::piece_move::PieceMove;
+
+/// A handle on the searches of a game
+///
+/// The handle can be cloned and sent to other threads to stop the search
+/// of the game or to follow its progress while it runs. A stop requested
+/// before the start of a search will stop it as soon as it starts, and the
+/// request is cleared at the end of the search, or with `clear` when the
+/// search had already ended.
+#[derive(Clone, Default)]
+pub struct SearchHandle {
+    pub(crate) is_stopped: Arc<AtomicBool>,
+    pub(crate) nodes_count: Arc<AtomicU64>, // Updated by the clock
+    is_running: Arc<AtomicBool>,
+    best_move: Arc<AtomicU16>,
+    depth: Arc<AtomicI8>,
+}
+
+impl SearchHandle {
+    /// Create a new `SearchHandle`
+    pub fn new() -> SearchHandle {
+        SearchHandle::default()
+    }
+
+    /// Stop the search
+    pub fn stop(&self) {
+        self.is_stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Clear a stop requested after the end of the last search, before
+    /// starting a new one
+    pub fn clear(&self) {
+        self.is_stopped.store(false, Ordering::Relaxed);
+    }
+
+    /// Check if the search has been stopped
+    pub fn is_stopped(&self) -> bool {
+        self.is_stopped.load(Ordering::Relaxed)
+    }
+
+    /// Check if a search is running
+    pub fn is_running(&self) -> bool {
+        self.is_running.load(Ordering::Relaxed)
+    }
+
+    /// Get the best move of the last iteration completed by the search
+    pub fn best_move(&self) -> Option<PieceMove> {
+        let m = self.best_move.load(Ordering::Relaxed);
+        let m = PieceMove::new((m >> 10) as Square, ((m >> 4) & 0b111111) as Square, (m & 0b1111) as PieceMoveType);
+        if m.is_null() { None } else { Some(m) }
+    }
+
+    /// Get the depth of the last iteration completed by the search
+    pub fn depth(&self) -> Depth {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of nodes searched by all the threads of the search
+    pub fn nodes_count(&self) -> u64 {
+        self.nodes_count.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn start(&self) {
+        self.is_running.store(true, Ordering::Relaxed);
+        self.best_move.store(0, Ordering::Relaxed);
+        self.depth.store(0, Ordering::Relaxed);
+        self.nodes_count.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn update(&self, best_move: PieceMove, depth: Depth) {
+        let m = ((best_move.from() as u16) << 10) | ((best_move.to() as u16) << 4) | best_move.kind() as u16;
+        self.best_move.store(m, Ordering::Relaxed);
+        self.depth.store(depth, Ordering::Relaxed);
+    }
+
+    pub(crate) fn finish(&self) {
+        self.is_stopped.store(false, Ordering::Relaxed);
+        self.is_running.store(false, Ordering::Relaxed);
+    }
+}
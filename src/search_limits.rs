@@ -0,0 +1,87 @@
+use std::prelude::v1::*;
+use std::cmp;
+
+use crate::common::*;
+use crate::clock::Clock;
+use crate::piece_move::PieceMove;
+
+/// Limits of a search
+///
+/// A search will stop at the first limit reached, and will use the clock of
+/// the game if no clock is given.
+#[derive(Clone, Default)]
+pub struct SearchLimits {
+    /// Maximum depth to search
+    pub depth: Option<Depth>,
+
+    /// Maximum number of nodes to search
+    pub nodes: Option<u64>,
+
+    /// Exact time to search in milliseconds
+    pub movetime: Option<u64>,
+
+    /// Search for a mate in the given number of moves
+    pub mate: Option<u16>,
+
+    /// Search until stopped
+    pub infinite: bool,
+
+    /// Restrict the search to these moves at the root
+    pub searchmoves: Vec<PieceMove>,
+
+    /// Clock replacing the clock of the game during the search
+    pub clock: Option<Clock>,
+
+    /// Search with a single thread, without time limits, and from cleared
+    /// tables to give the same result for the same position and limits
+    pub deterministic: bool,
+}
+
+impl SearchLimits {
+    /// Create new unbounded `SearchLimits`
+    pub fn new() -> SearchLimits {
+        SearchLimits::default()
+    }
+
+    /// Get the maximum depth to search
+    pub fn max_depth(&self) -> Depth {
+        match self.depth {
+            Some(depth) => depth.clamp(1, (MAX_PLY - 10) as Depth),
+            None => (MAX_PLY - 10) as Depth,
+        }
+    }
+
+    /// Get the score a mate in the given number of moves would have
+    pub fn mate_score(&self) -> Option<Score> {
+        self.mate.map(|n| INF - (cmp::min(2 * n as usize, MAX_PLY) as Score) + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_depth() {
+        let mut limits = SearchLimits::new();
+        assert_eq!(limits.max_depth(), (MAX_PLY - 10) as Depth);
+
+        limits.depth = Some(8);
+        assert_eq!(limits.max_depth(), 8);
+
+        limits.depth = Some(0);
+        assert_eq!(limits.max_depth(), 1);
+    }
+
+    #[test]
+    fn test_mate_score() {
+        let mut limits = SearchLimits::new();
+        assert_eq!(limits.mate_score(), None);
+
+        limits.mate = Some(1);
+        assert_eq!(limits.mate_score(), Some(INF - 1));
+
+        limits.mate = Some(3);
+        assert_eq!(limits.mate_score(), Some(INF - 5));
+    }
+}
@@ -0,0 +1,178 @@
+use std::prelude::v1::*;
+
+use crate::color::*;
+use crate::common::*;
+use crate::attack::Attack;
+use crate::game::Game;
+use crate::piece_move::PieceMove;
+use crate::piece_move_generator::PieceMoveGenerator;
+use crate::piece_move_notation::PieceMoveNotation;
+use crate::search::{Search, SearchLine};
+
+pub use crate::transposition::Bound;
+
+/// Progress of a line searched from the root position
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchInfo {
+    /// Depth of the current iteration
+    pub depth: Depth,
+
+    /// Maximum ply reached by the search
+    pub seldepth: usize,
+
+    /// Index of the line starting at 1 when searching multiple lines
+    pub multipv: usize,
+
+    /// Score of the line from the side to move
+    pub score: Score,
+
+    /// Bound of the score when the line failed high or low
+    pub bound: Bound,
+
+    /// Number of nodes searched by all the threads
+    pub nodes: u64,
+
+    /// Time elapsed since the start of the search in milliseconds
+    pub time: u64,
+
+    /// Permill of the transposition table used by the search
+    pub hashfull: usize,
+
+    /// Number of positions found in the endgame tablebases
+    pub tbhits: u64,
+
+    /// Principal variation of the line
+    pub pv: Vec<PieceMove>,
+}
+
+/// Observe the progress of a search
+///
+/// An observer can be given to a game to receive the events of its searches
+/// instead of reading their text output. The game is always given at the
+/// root position of the search, and every method does nothing by default.
+pub trait SearchObserver: Send + Sync {
+    /// Called before the first iteration of a search
+    fn on_start(&self, _game: &Game) {}
+
+    /// Called when a line is found or fails low during an iteration of a
+    /// verbose search
+    fn on_line(&self, _game: &Game, _info: &SearchInfo) {}
+
+    /// Called before the search of the root move `m` numbered from 1 in the
+    /// current iteration
+    fn on_current_move(&self, _game: &Game, _depth: Depth, _m: PieceMove, _number: usize) {}
+
+    /// Called with the best lines found at the end of a search
+    fn on_finish(&self, _game: &Game, _lines: &[SearchLine]) {}
+}
+
+/// Get the standard algebraic notation of a PV played from the game position
+pub fn pv_to_san(game: &Game, pv: &[PieceMove]) -> String {
+    let mut game = game.clone();
+    let mut res = Vec::with_capacity(2 * pv.len());
+    if game.side() == BLACK && !pv.is_empty() {
+        res.push(format!("{}. ...", game.positions.fullmoves()));
+    }
+    for &m in pv {
+        let side = game.side();
+        if side == WHITE {
+            res.push(format!("{}.", game.positions.fullmoves()));
+        }
+        let mut san = game.move_to_san(m);
+        game.make_move(m);
+        if game.is_check(side ^ 1) {
+            san.push(if game.is_mate() { '#' } else { '+' });
+        }
+        res.push(san);
+    }
+    res.join(" ")
+}
+
+/// Get the long algebraic notation of a PV
+pub fn pv_to_lan(pv: &[PieceMove]) -> String {
+    pv.iter().map(|m| m.to_lan()).collect::<Vec<String>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::clock::Clock;
+    use crate::fen::FEN;
+    use crate::piece::PieceAttr;
+    use crate::search_limits::SearchLimits;
+
+    #[derive(Default)]
+    struct Counter {
+        starts: AtomicUsize,
+        lines: AtomicUsize,
+        moves: AtomicUsize,
+        finishes: AtomicUsize,
+        depth: AtomicUsize,
+        seldepth: AtomicUsize,
+    }
+
+    impl SearchObserver for Counter {
+        fn on_start(&self, _game: &Game) {
+            self.starts.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_line(&self, _game: &Game, info: &SearchInfo) {
+            self.lines.fetch_add(1, Ordering::Relaxed);
+            self.depth.fetch_max(info.depth as usize, Ordering::Relaxed);
+            self.seldepth.fetch_max(info.seldepth, Ordering::Relaxed);
+        }
+
+        fn on_current_move(&self, game: &Game, _depth: Depth, m: PieceMove, number: usize) {
+            assert_eq!(game.board[m.from() as usize].color(), game.side());
+            assert!(number > 0);
+            self.moves.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_finish(&self, _game: &Game, lines: &[SearchLine]) {
+            assert_eq!(lines.len(), 1);
+            self.finishes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_search_observer() {
+        let counter = Arc::new(Counter::default());
+        let mut game = Game::from_fen(DEFAULT_FEN).unwrap();
+        game.observer = Some(counter.clone());
+        game.is_search_verbose = true;
+        let limits = SearchLimits {
+            depth: Some(5),
+            clock: Some(Clock::new(1, 5 * 1000)), // 5 seconds
+            ..SearchLimits::new()
+        };
+        game.search(limits);
+
+        assert_eq!(counter.starts.load(Ordering::Relaxed), 1);
+        assert_eq!(counter.finishes.load(Ordering::Relaxed), 1);
+        assert!(counter.lines.load(Ordering::Relaxed) >= 5);
+        assert!(counter.moves.load(Ordering::Relaxed) >= 5 * 20);
+        assert_eq!(counter.depth.load(Ordering::Relaxed), 5);
+        assert!(counter.seldepth.load(Ordering::Relaxed) >= 5);
+    }
+
+    #[test]
+    fn test_pv_to_san() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 2 3";
+        let mut game = Game::from_fen(fen).unwrap();
+        let pv = vec![game.move_from_lan("f3f7")];
+        assert_eq!(pv_to_san(&game, &pv), "3. Qxf7#");
+        assert_eq!(pv_to_lan(&pv), "f3f7");
+
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        let mut game = Game::from_fen(fen).unwrap();
+        let pv = vec![game.move_from_lan("g1f3"), game.move_from_lan("b8c6")];
+        assert_eq!(pv_to_san(&game, &pv), "2. Nf3 Nc6");
+
+        game.make_move(pv[0]);
+        let pv = vec![pv[1], game.move_from_lan("f1b5")];
+        assert_eq!(pv_to_san(&game, &pv), "2. ... Nc6 3. Bb5");
+        assert_eq!(pv_to_san(&game, &[]), "");
+    }
+}
@@ -0,0 +1,164 @@
+use std::prelude::v1::*;
+use std::cmp;
+
+use rand::{RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use crate::common::*;
+use crate::search::SearchLine;
+
+/// Skill level of the engine at full strength
+pub const MAX_SKILL_LEVEL: u8 = 20;
+
+/// Elo rating of the lowest skill level
+pub const MIN_ELO: u16 = 800;
+
+/// Elo rating of the highest skill level
+pub const MAX_ELO: u16 = 2000;
+
+// Elo rating between two skill levels
+const ELO_PER_LEVEL: u16 = (MAX_ELO - MIN_ELO) / MAX_SKILL_LEVEL as u16;
+
+// Number of lines searched to choose a weaker move from
+const SKILL_MULTIPV: usize = 4;
+
+// Maximum difference with the best score used to add noise to the lines
+const MAX_NOISE: i32 = 100;
+
+const SEED: u64 = 0x5EED;
+
+/// Skill level limiting the strength of the engine
+///
+/// A skill level below the maximum limits the depth and the number of nodes
+/// of the searches, and plays a weaker line chosen among the best lines with
+/// some noise added to their scores, as a human would make mistakes.
+#[derive(Clone)]
+pub struct Skill {
+    level: u8,
+    rng: XorShiftRng,
+}
+
+impl Skill {
+    /// Create a new `Skill` at the given level from 0 to `MAX_SKILL_LEVEL`
+    pub fn new(level: u8) -> Skill {
+        Skill {
+            level: cmp::min(level, MAX_SKILL_LEVEL),
+            rng: XorShiftRng::seed_from_u64(SEED),
+        }
+    }
+
+    /// Create a new `Skill` with the level closest to the given Elo rating
+    pub fn from_elo(elo: u16) -> Skill {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let level = (elo - MIN_ELO + ELO_PER_LEVEL / 2) / ELO_PER_LEVEL;
+        Skill::new(level as u8)
+    }
+
+    /// Get the skill level
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Get the approximate Elo rating of the skill level
+    pub fn elo(&self) -> u16 {
+        MIN_ELO + self.level as u16 * ELO_PER_LEVEL
+    }
+
+    /// Check if the skill level limits the strength of the engine
+    pub fn is_enabled(&self) -> bool {
+        self.level < MAX_SKILL_LEVEL
+    }
+
+    /// Seed the random choices of the weaker lines
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = XorShiftRng::seed_from_u64(seed);
+    }
+
+    /// Get the maximum depth of a search
+    pub fn max_depth(&self) -> Depth {
+        1 + (self.level / 2) as Depth
+    }
+
+    /// Get the maximum number of nodes of a search
+    pub fn max_nodes(&self) -> u64 {
+        1000 << (self.level / 2)
+    }
+
+    /// Get the number of lines to search
+    pub fn multipv(&self) -> usize {
+        SKILL_MULTIPV
+    }
+
+    /// Choose the index of the line to play among the best lines sorted from
+    /// best to worst
+    ///
+    /// Each line gets a bonus growing with the weakness of the level and its
+    /// distance to the best score, plus a random noise up to the difference
+    /// between the best and the worst scores.
+    pub fn pick_line(&mut self, lines: &[SearchLine]) -> usize {
+        if lines.len() < 2 {
+            return 0;
+        }
+        let weakness = 120 - 5 * self.level as i32;
+        let top = lines[0].score as i32;
+        let noise = cmp::min(top - lines[lines.len() - 1].score as i32, MAX_NOISE);
+        let mut best = 0;
+        let mut best_score = i32::MIN;
+        for (i, line) in lines.iter().enumerate() {
+            let score = line.score as i32;
+            let rand = (self.rng.next_u32() % weakness as u32) as i32;
+            let push = (weakness * (top - score) + noise * rand) / 128;
+            if score + push >= best_score {
+                best_score = score + push;
+                best = i;
+            }
+        }
+        best
+    }
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Skill::new(MAX_SKILL_LEVEL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece_move::PieceMove;
+
+    #[test]
+    fn test_from_elo() {
+        assert_eq!(Skill::from_elo(0).level(), 0);
+        assert_eq!(Skill::from_elo(MIN_ELO).level(), 0);
+        assert_eq!(Skill::from_elo(1400).level(), 10);
+        assert_eq!(Skill::from_elo(MAX_ELO).level(), MAX_SKILL_LEVEL);
+        assert_eq!(Skill::from_elo(3000).level(), MAX_SKILL_LEVEL);
+        assert_eq!(Skill::new(10).elo(), 1400);
+        assert!(!Skill::default().is_enabled());
+        assert!(Skill::new(19).is_enabled());
+    }
+
+    #[test]
+    fn test_pick_line() {
+        let line = |score| SearchLine { best_move: PieceMove::new_null(), score, depth: 1, pv: vec![] };
+
+        // Never play a line much worse than the best at a medium level
+        let lines = vec![line(50), line(-400), line(-500)];
+        let mut skill = Skill::new(10);
+        for _ in 0..100 {
+            assert_eq!(skill.pick_line(&lines), 0);
+        }
+
+        // Sometimes play a weaker line close to the best at the lowest level
+        let mut skill = Skill::new(0);
+        let lines = vec![line(50), line(40), line(30), line(0)];
+        let mut picks = [0; 4];
+        for _ in 0..100 {
+            picks[skill.pick_line(&lines)] += 1;
+        }
+        assert!(picks[0] < 100);
+        assert!(picks[1..].iter().sum::<usize>() > 0);
+    }
+}
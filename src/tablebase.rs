@@ -0,0 +1,912 @@
+use std::prelude::v1::*;
+use std::cmp::{self, Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::Arc;
+
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::color::*;
+use crate::piece::*;
+use crate::square::*;
+use crate::common::*;
+use crate::attack::{Attack, piece_attacks, PAWN_ATTACKS};
+use crate::bitboard::{Bitboard, BitboardExt, BitboardIterator};
+use crate::game::Game;
+use crate::piece::{PieceAttr, PieceChar};
+use crate::piece_move_generator::PieceMoveGenerator;
+use crate::positions::Position;
+
+/// Maximum number of pieces, kings included, in a tablebase
+pub const TB_MAX_PIECES: usize = 4;
+
+/// Extension of the tablebase files
+pub const TB_EXTENSION: &str = "lwtb";
+
+const TB_MAGIC: &[u8; 4] = b"LWTB";
+const TB_VERSION: u8 = 1;
+
+// Order of the pieces of a side in a material signature like "KRPvK"
+const PIECES_ORDER: [Piece; 6] = [KING, QUEEN, ROOK, BISHOP, KNIGHT, PAWN];
+
+// Mark impossible positions in the moves counters of the generator
+const INVALID: u8 = u8::MAX;
+
+/// Distance to mate in plies of a position for the side to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dtm {
+    Win(u8),
+    Draw,
+    Loss(u8),
+}
+
+impl Dtm {
+    // Each position is stored in one byte: zero for a draw, and the distance
+    // to mate plus one otherwise, which is even for a win and odd for a loss.
+    fn from_byte(b: u8) -> Dtm {
+        if b == 0 {
+            Dtm::Draw
+        } else if b & 1 == 0 {
+            Dtm::Win(b - 1)
+        } else {
+            Dtm::Loss(b - 1)
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Dtm::Win(d) | Dtm::Loss(d) => d.saturating_add(1),
+            Dtm::Draw => 0,
+        }
+    }
+
+    /// Get the result of the previous position for the side that played the
+    /// move leading to this one
+    pub fn parent(self) -> Dtm {
+        match self {
+            Dtm::Win(d) => Dtm::Loss(d.saturating_add(1)),
+            Dtm::Loss(d) => Dtm::Win(d.saturating_add(1)),
+            Dtm::Draw => Dtm::Draw,
+        }
+    }
+
+    /// Get the search score of the position found at the given ply
+    pub fn score(self, ply: usize) -> Score {
+        match self {
+            Dtm::Win(d) => INF - (ply as Score) - (d as Score),
+            Dtm::Loss(d) => -INF + (ply as Score) + (d as Score),
+            Dtm::Draw => 0,
+        }
+    }
+
+    // Faster wins and slower losses are better
+    fn key(self) -> i16 {
+        match self {
+            Dtm::Win(d) => 1000 - (d as i16),
+            Dtm::Draw => 0,
+            Dtm::Loss(d) => -1000 + (d as i16),
+        }
+    }
+}
+
+impl Ord for Dtm {
+    fn cmp(&self, other: &Dtm) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for Dtm {
+    fn partial_cmp(&self, other: &Dtm) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Numeric signature of the material of a side, with the number of pieces of
+// each kind in 4 bits, to find the tables of positions without allocations
+fn side_signature(game: &Game, side: Color) -> u64 {
+    let mut signature = 0;
+    for &p in &PIECES_ORDER {
+        signature += (game.bitboard(side | p).count() as u64) << (4 * (p >> 1));
+    }
+    signature
+}
+
+// Numeric signature of a material like "KQvKR", with the signature of the
+// first side in the lower 32 bits and the other in the upper 32 bits
+fn material_signature(material: &str) -> u64 {
+    let mut signature = 0;
+    for (i, side) in material.split('v').enumerate() {
+        for c in side.chars() {
+            signature += 1 << (4 * (Piece::from_char(c).kind() >> 1) + 32 * i as Piece);
+        }
+    }
+    signature
+}
+
+fn material_value(material: &str) -> u32 {
+    material.chars().map(|c| match c {
+        'Q' => 9,
+        'R' => 5,
+        'B' | 'N' => 3,
+        'P' => 1,
+        _ => 0,
+    }).sum()
+}
+
+// Get the signature of the material of both sides, with the strongest side
+// first, and whether or not the sides have been swapped.
+fn material_key(a: &str, b: &str) -> (String, bool) {
+    if (material_value(a), a) >= (material_value(b), b) {
+        (format!("{}v{}", a, b), false)
+    } else {
+        (format!("{}v{}", b, a), true)
+    }
+}
+
+// Parse a material signature like "KQvKR" into the canonical one
+fn parse_material(material: &str) -> Result<String, String> {
+    let err = format!("invalid material '{}'", material);
+    let material = material.to_uppercase();
+    let sides: Vec<String> = material.split('V').map(|side| {
+        let mut pieces: Vec<char> = side.chars().collect();
+        pieces.sort_by_key(|&c| PIECES_ORDER.iter().position(|&p| p.to_char() == c));
+        pieces.into_iter().collect()
+    }).collect();
+
+    if sides.len() != 2 {
+        return Err(err);
+    }
+    for side in &sides {
+        let is_valid =
+            side.starts_with('K') &&
+            side.chars().skip(1).all(|c| "QRBNP".contains(c));
+        if !is_valid {
+            return Err(err);
+        }
+    }
+    let n = sides[0].len() + sides[1].len();
+    if !(3..=TB_MAX_PIECES).contains(&n) {
+        return Err(err);
+    }
+
+    Ok(material_key(&sides[0], &sides[1]).0)
+}
+
+// Get the materials reachable by a capture or a promotion
+fn material_children(material: &str) -> Vec<String> {
+    let sides: Vec<&str> = material.split('v').collect();
+    let mut children = Vec::new();
+    for i in 0..2 {
+        let (side, other) = (sides[i], sides[i ^ 1]);
+        for (j, c) in side.char_indices().skip(1) {
+            let mut captured = String::from(side);
+            captured.remove(j);
+            children.push(material_key(&captured, other).0);
+            if c == 'P' {
+                for promoted in &["Q", "R", "B", "N"] {
+                    let side = side.replacen('P', promoted, 1);
+                    if let Ok(child) = parse_material(&format!("{}v{}", side, other)) {
+                        children.push(child);
+                    }
+                }
+            }
+        }
+    }
+    children.retain(|child| child.len() > 3); // Ignore "KvK"
+    children.sort();
+    children.dedup();
+    children
+}
+
+/// List every material signature of 3 and 4 pieces
+pub fn tablebases_materials() -> Vec<String> {
+    let mut materials = Vec::new();
+    let pieces = ["Q", "R", "B", "N", "P"];
+    for a in &pieces {
+        materials.push(parse_material(&format!("K{}vK", a)).unwrap());
+        for b in &pieces {
+            materials.push(parse_material(&format!("K{}{}vK", a, b)).unwrap());
+            materials.push(parse_material(&format!("K{}vK{}", a, b)).unwrap());
+        }
+    }
+    materials.sort_by_key(|m| (m.len(), m.clone()));
+    materials.dedup();
+    materials
+}
+
+// The values of a table are saved as the differences between consecutive
+// values, which are small for neighbor positions, with a canonical Huffman
+// code given by the lengths of the codes of the 256 symbols.
+fn huffman_encode(values: &[u8]) -> Vec<u8> {
+    let mut freqs = [0u64; 256];
+    let mut prev = 0u8;
+    for &v in values {
+        freqs[v.wrapping_sub(prev) as usize] += 1;
+        prev = v;
+    }
+    let lengths = huffman_lengths(freqs);
+    let codes = huffman_codes(&lengths);
+
+    let mut bytes = lengths.to_vec();
+    let mut buf = 0u64;
+    let mut n = 0;
+    let mut prev = 0u8;
+    for &v in values {
+        let s = v.wrapping_sub(prev) as usize;
+        prev = v;
+        buf = (buf << lengths[s]) | codes[s] as u64;
+        n += lengths[s];
+        while n >= 8 {
+            n -= 8;
+            bytes.push((buf >> n) as u8);
+        }
+    }
+    if n > 0 {
+        bytes.push((buf << (8 - n)) as u8);
+    }
+    bytes
+}
+
+fn huffman_decode(lengths: &[u8], bytes: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut symbols: Vec<u8> = (0..=255).filter(|&s| lengths[s as usize] > 0).collect();
+    symbols.sort_by_key(|&s| lengths[s as usize]);
+    let mut first = [0u32; 33];
+    let mut count = [0u32; 33];
+    let mut offset = [0u32; 33];
+    for &s in &symbols {
+        count[lengths[s as usize] as usize] += 1;
+    }
+    let mut code = 0;
+    let mut index = 0;
+    for len in 1..33 {
+        first[len] = code;
+        offset[len] = index;
+        code = (code + count[len]) << 1;
+        index += count[len];
+    }
+
+    let mut values = Vec::with_capacity(size);
+    let mut prev = 0u8;
+    let mut code = 0u32;
+    let mut len = 0;
+    for &b in bytes {
+        for i in (0..8).rev() {
+            code = (code << 1) | ((b >> i) & 1) as u32;
+            len += 1;
+            if len > 32 {
+                return None;
+            }
+            if code >= first[len] && code - first[len] < count[len] {
+                let s = symbols[(offset[len] + code - first[len]) as usize];
+                prev = prev.wrapping_add(s);
+                values.push(prev);
+                if values.len() == size {
+                    return Some(values);
+                }
+                code = 0;
+                len = 0;
+            }
+        }
+    }
+    None
+}
+
+// Get the lengths of the codes of a Huffman tree built from the frequencies
+// of the symbols, flattening the frequencies until no code is longer than 32.
+fn huffman_lengths(mut freqs: [u64; 256]) -> [u8; 256] {
+    loop {
+        let mut lengths = [0u8; 256];
+        let mut parents = vec![usize::MAX; 256];
+        let mut heap = BinaryHeap::new();
+        for (s, &f) in freqs.iter().enumerate() {
+            if f > 0 {
+                heap.push(Reverse((f, s)));
+            }
+        }
+        if heap.len() == 1 {
+            let Reverse((_, s)) = heap.pop().unwrap();
+            lengths[s] = 1;
+            return lengths;
+        }
+        while heap.len() > 1 {
+            let Reverse((fa, a)) = heap.pop().unwrap();
+            let Reverse((fb, b)) = heap.pop().unwrap();
+            let node = parents.len();
+            parents.push(usize::MAX);
+            parents[a] = node;
+            parents[b] = node;
+            heap.push(Reverse((fa + fb, node)));
+        }
+        for s in 0..256 {
+            let mut node = s;
+            while freqs[s] > 0 && parents[node] != usize::MAX {
+                node = parents[node];
+                lengths[s] += 1;
+            }
+        }
+        if lengths.iter().all(|&len| len <= 32) {
+            return lengths;
+        }
+        for f in freqs.iter_mut().filter(|f| **f > 0) {
+            *f = *f / 2 + 1;
+        }
+    }
+}
+
+fn huffman_codes(lengths: &[u8; 256]) -> [u32; 256] {
+    let mut codes = [0; 256];
+    let mut code = 0;
+    for len in 1..33 {
+        for s in 0..256 {
+            if lengths[s] as usize == len {
+                codes[s] = code;
+                code += 1;
+            }
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+// A table of the distances to mate of every position of a given material.
+//
+// The strongest side is always white in the table, and the position is
+// mirrored to put its king on the files A to D, and also on the ranks 1 to 4
+// when there's no pawns, because the result of a position doesn't change
+// with those symmetries.
+#[derive(Clone)]
+struct Tablebase {
+    material: String,
+    pieces: Vec<Piece>, // Pieces of the strongest side first, kings first
+    has_pawns: bool,
+    values: Arc<Vec<u8>>,
+}
+
+impl Tablebase {
+    fn new(material: &str) -> Tablebase {
+        let mut pieces = Vec::new();
+        for (i, side) in material.split('v').enumerate() {
+            for c in side.chars() {
+                pieces.push(Piece::from_char(c) | (i as Color));
+            }
+        }
+        let has_pawns = pieces.iter().any(|p| p.is_pawn());
+        Tablebase { material: material.into(), pieces, has_pawns, values: Arc::new(Vec::new()) }
+    }
+
+    fn kings_squares_count(&self) -> usize {
+        if self.has_pawns { 32 } else { 16 }
+    }
+
+    fn len(&self) -> usize {
+        let n = self.pieces.len() as u32;
+        2 * self.kings_squares_count() * 64usize.pow(n - 1)
+    }
+
+    // Get the index of a position given by the squares of its pieces in the
+    // order of the table, and the side to move.
+    fn index(&self, squares: &[Square], side: Color) -> usize {
+        let n = self.pieces.len();
+        let mut flip = 0;
+        if squares[0].file() > 3 {
+            flip ^= 7;
+        }
+        if !self.has_pawns && squares[0].rank() > 3 {
+            flip ^= 56;
+        }
+        let mut sqs = [0; TB_MAX_PIECES];
+        for i in 0..n {
+            sqs[i] = squares[i] ^ flip;
+        }
+        for i in 2..n {
+            if self.pieces[i] == self.pieces[i - 1] && sqs[i] < sqs[i - 1] {
+                sqs.swap(i, i - 1);
+            }
+        }
+
+        let k = (sqs[0].rank() * 4 + sqs[0].file()) as usize;
+        let mut index = (side as usize) * self.kings_squares_count() + k;
+        for &sq in sqs.iter().take(n).skip(1) {
+            index = index * 64 + sq as usize;
+        }
+        index
+    }
+
+    // Get the position of an index
+    fn position(&self, mut index: usize) -> ([Square; TB_MAX_PIECES], Color) {
+        let n = self.pieces.len();
+        let mut squares = [0; TB_MAX_PIECES];
+        for i in (1..n).rev() {
+            squares[i] = (index % 64) as Square;
+            index /= 64;
+        }
+        let k = index % self.kings_squares_count();
+        squares[0] = ((k / 4) * 8 + k % 4) as Square;
+        let side = (index / self.kings_squares_count()) as Color;
+        (squares, side)
+    }
+
+    // Check that the pieces of a position are on distinct squares, with
+    // pawns outside of the first and last ranks, and that the position is
+    // the one used for its index.
+    fn is_valid(&self, squares: &[Square], side: Color, index: usize) -> bool {
+        let mut occupied: Bitboard = 0;
+        for (&sq, piece) in squares.iter().zip(&self.pieces) {
+            if occupied.get(sq) {
+                return false;
+            }
+            if piece.is_pawn() && (sq.rank() == 0 || sq.rank() == 7) {
+                return false;
+            }
+            occupied.set(sq);
+        }
+        self.index(squares, side) == index
+    }
+
+    // Check if the king of the given side is attacked in a position
+    fn is_check(&self, squares: &[Square], side: Color) -> bool {
+        let n = self.pieces.len();
+        let mut occupied: Bitboard = 0;
+        for &sq in squares.iter().take(n) {
+            occupied.set(sq);
+        }
+        let king = squares[self.pieces.iter().position(|&p| p == side | KING).unwrap()];
+        (0..n).any(|i| {
+            let p = self.pieces[i];
+            p.color() != side && piece_attacks(p, squares[i], occupied).get(king)
+        })
+    }
+
+    // Put a position on the board of a game
+    fn setup(&self, game: &mut Game, squares: &[Square], side: Color) {
+        game.bitboards = [0; 14];
+        game.board = [EMPTY; 64];
+        for (&p, &sq) in self.pieces.iter().zip(squares) {
+            game.board[sq as usize] = p;
+            game.bitboards[p as usize].set(sq);
+            game.bitboards[p.color() as usize].set(sq);
+        }
+        let mut position = Position::new();
+        position.side = side;
+        game.positions.clear();
+        game.positions.push(position);
+    }
+
+    // Get the indexes of the positions leading to the given position with a
+    // move that is neither a capture nor a promotion.
+    fn predecessors(&self, squares: &[Square], side: Color) -> Vec<usize> {
+        let n = self.pieces.len();
+        let mut occupied: Bitboard = 0;
+        for &sq in squares.iter().take(n) {
+            occupied.set(sq);
+        }
+
+        let mut res = Vec::new();
+        let mut prev = [0; TB_MAX_PIECES];
+        prev[..n].copy_from_slice(&squares[..n]);
+        for i in 0..n {
+            let p = self.pieces[i];
+            if p.color() == side {
+                continue;
+            }
+            let sq = squares[i];
+            let mut origins: Bitboard = if p.is_pawn() {
+                let (up, rank) = if p.color() == WHITE { (-8, 3) } else { (8, 4) };
+                let mut origins = 0;
+                let from = ((sq as i8) + up) as Square;
+                let is_first_rank = from.rank() == 0 || from.rank() == 7;
+                if !occupied.get(from) && !is_first_rank {
+                    origins |= Bitboard::from_square(from);
+                    let from = ((from as i8) + up) as Square;
+                    if sq.rank() == rank && !occupied.get(from) {
+                        origins |= Bitboard::from_square(from);
+                    }
+                }
+                origins
+            } else {
+                piece_attacks(p, sq, occupied) & !occupied
+            };
+            while let Some(from) = origins.next() {
+                prev[i] = from;
+                if !self.is_check(&prev, side) {
+                    res.push(self.index(&prev, side ^ 1));
+                }
+            }
+            prev[i] = sq;
+        }
+        res
+    }
+}
+
+/// Endgame tablebases giving the distance to mate of positions with a few
+/// pieces
+#[derive(Clone, Default)]
+pub struct Tablebases {
+    tables: BTreeMap<u64, Tablebase>, // Indexed by material signature
+}
+
+impl Tablebases {
+    /// Create empty `Tablebases`
+    pub fn new() -> Tablebases {
+        Tablebases::default()
+    }
+
+    /// Get the number of tables
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Get the material signatures of the tables, like "KQvKR"
+    pub fn materials(&self) -> Vec<String> {
+        let mut materials: Vec<String> = self.tables.values().map(|tb| tb.material.clone()).collect();
+        materials.sort();
+        materials
+    }
+
+    /// Get the distance to mate of the current position of a game
+    pub fn probe(&self, game: &Game) -> Option<Dtm> {
+        let occupied = game.bitboard(WHITE) | game.bitboard(BLACK);
+        let n = occupied.count() as usize;
+        if n > TB_MAX_PIECES {
+            return None;
+        }
+        if n == 2 {
+            return Some(Dtm::Draw);
+        }
+        if self.tables.is_empty() {
+            return None;
+        }
+
+        // Tables don't have castling rights or en passant captures
+        let side = game.side();
+        let position = game.positions.top();
+        if position.castling_rights != 0 {
+            return None;
+        }
+        let ep = position.en_passant;
+        if ep != OUT && PAWN_ATTACKS[(side ^ 1) as usize][ep as usize] & game.bitboard(side | PAWN) != 0 {
+            return None;
+        }
+
+        // The table is stored with the strongest side first
+        let white = side_signature(game, WHITE);
+        let black = side_signature(game, BLACK);
+        let (tb, is_swapped) = match self.tables.get(&(white | (black << 32))) {
+            Some(tb) => (tb, false),
+            None => (self.tables.get(&(black | (white << 32)))?, true),
+        };
+
+        let strong = if is_swapped { BLACK } else { WHITE };
+        let flip = if is_swapped { 56 } else { 0 };
+        let mut squares = [0; TB_MAX_PIECES];
+        let mut i = 0;
+        for &c in &[strong, strong ^ 1] {
+            for &p in &PIECES_ORDER {
+                let mut pieces = *game.bitboard(c | p);
+                while let Some(sq) = pieces.next() {
+                    squares[i] = sq ^ flip;
+                    i += 1;
+                }
+            }
+        }
+
+        let index = tb.index(&squares[..n], side ^ strong);
+        Some(Dtm::from_byte(tb.values[index]))
+    }
+
+    /// Generate the table of the given material, and the tables of the
+    /// materials it can lead to, returning the new materials generated
+    pub fn generate(&mut self, material: &str) -> Result<Vec<String>, String> {
+        let material = parse_material(material)?;
+        let mut generated = Vec::new();
+        if self.tables.contains_key(&material_signature(&material)) {
+            return Ok(generated);
+        }
+        for child in material_children(&material) {
+            generated.extend(self.generate(&child)?);
+        }
+        let tb = self.retrograde(&material);
+        self.tables.insert(material_signature(&material), tb);
+        generated.push(material);
+        Ok(generated)
+    }
+
+    // Generate a table by retrograde analysis: mates are found first, then
+    // every position leading to a lost position is won, and every position
+    // with only moves leading to won positions is lost, one ply at a time.
+    fn retrograde(&self, material: &str) -> Tablebase {
+        let mut tb = Tablebase::new(material);
+        let size = tb.len();
+        let mut values = vec![0; size];
+
+        // Number of moves staying in the table and leading to unresolved
+        // positions, and best result of the other moves.
+        let mut counts = vec![INVALID; size];
+        let mut conversions: Vec<Option<Dtm>> = vec![None; size];
+
+        let mut game = Game::new();
+        game.tt_resize(0);
+        game.moves.skip_ordering = true;
+
+        let mut last_ply = 0;
+        for index in 0..size {
+            let (squares, side) = tb.position(index);
+            if !tb.is_valid(&squares, side, index) {
+                continue;
+            }
+            tb.setup(&mut game, &squares, side);
+            if game.is_check(side ^ 1) {
+                continue;
+            }
+
+            let mut legal_moves_count = 0;
+            let mut moves_count = 0;
+            let mut best = None;
+            game.moves.clear();
+            while let Some(m) = game.next_move() {
+                game.make_move(m);
+                if !game.is_check(side) {
+                    legal_moves_count += 1;
+                    if m.is_capture() || m.is_promotion() {
+                        let dtm = self.probe(&game).expect("missing tablebase").parent();
+                        best = cmp::max(best, Some(dtm));
+                    } else {
+                        moves_count += 1;
+                    }
+                }
+                game.undo_move(m);
+            }
+
+            counts[index] = moves_count;
+            conversions[index] = best;
+            let value = if legal_moves_count == 0 {
+                if game.is_check(side) { Dtm::Loss(0) } else { Dtm::Draw }
+            } else if moves_count == 0 {
+                best.unwrap()
+            } else {
+                continue;
+            };
+            values[index] = value.to_byte();
+            last_ply = cmp::max(last_ply, value.to_byte());
+        }
+        for dtm in conversions.iter().flatten() {
+            if let Dtm::Win(_) = dtm {
+                last_ply = cmp::max(last_ply, dtm.to_byte());
+            }
+        }
+
+        let mut ply = 0;
+        while ply < last_ply {
+            let b = ply + 1;
+
+            // Positions won with a capture or a promotion at this ply
+            for index in 0..size {
+                if values[index] == 0 && conversions[index] == Some(Dtm::Win(ply)) {
+                    values[index] = b;
+                }
+            }
+
+            for index in 0..size {
+                if values[index] != b {
+                    continue;
+                }
+                let (squares, side) = tb.position(index);
+                let is_loss = b % 2 == 1;
+                for i in tb.predecessors(&squares, side) {
+                    if values[i] != 0 || counts[i] == INVALID {
+                        continue;
+                    }
+                    if is_loss {
+                        values[i] = Dtm::Win(ply + 1).to_byte();
+                        last_ply = cmp::max(last_ply, values[i]);
+                        continue;
+                    }
+                    counts[i] -= 1;
+                    if counts[i] > 0 {
+                        continue;
+                    }
+                    let dtm = match conversions[i] {
+                        None => Dtm::Loss(ply + 1),
+                        Some(Dtm::Loss(d)) => Dtm::Loss(cmp::max(d, ply + 1)),
+                        Some(_) => continue, // Draw or win by conversion
+                    };
+                    values[i] = dtm.to_byte();
+                    last_ply = cmp::max(last_ply, values[i]);
+                }
+            }
+            ply += 1;
+        }
+
+        // Impossible positions are never probed so they can take the value
+        // of the previous position to make the table easier to compress.
+        for index in 1..size {
+            if counts[index] == INVALID {
+                values[index] = values[index - 1];
+            }
+        }
+
+        tb.values = Arc::new(values);
+        tb
+    }
+
+    /// Load a table from its file content, returning its material
+    pub fn load(&mut self, bytes: &[u8]) -> Result<String, String> {
+        let err = "invalid tablebase";
+        if bytes.len() < 6 || &bytes[0..4] != TB_MAGIC || bytes[4] != TB_VERSION {
+            return Err(err.into());
+        }
+        let n = bytes[5] as usize;
+        let material = bytes.get(6..6 + n).ok_or(err)?;
+        let material = String::from_utf8(material.to_vec()).map_err(|_| err)?;
+        if parse_material(&material)? != material {
+            return Err(err.into());
+        }
+        let i = 6 + n;
+        let lengths = bytes.get(i..i + 256).ok_or(err)?;
+        if lengths.iter().any(|&len| len > 32) {
+            return Err(err.into());
+        }
+        let mut tb = Tablebase::new(&material);
+        let values = huffman_decode(lengths, &bytes[i + 256..], tb.len()).ok_or(err)?;
+        tb.values = Arc::new(values);
+        self.tables.insert(material_signature(&material), tb);
+        Ok(material)
+    }
+
+    /// Save the table of the given material to its file content
+    pub fn save(&self, material: &str) -> Option<Vec<u8>> {
+        let tb = self.tables.get(&material_signature(material))?;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(TB_MAGIC);
+        bytes.push(TB_VERSION);
+        bytes.push(material.len() as u8);
+        bytes.extend_from_slice(material.as_bytes());
+        bytes.extend(huffman_encode(&tb.values));
+        Some(bytes)
+    }
+
+    /// Load every table found in a directory, returning the number of tables
+    /// loaded
+    #[cfg(feature = "std")]
+    pub fn load_dir(&mut self, path: &Path) -> usize {
+        let mut n = 0;
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension() == Some(TB_EXTENSION.as_ref()) {
+                    if let Ok(bytes) = fs::read(&path) {
+                        if self.load(&bytes).is_ok() {
+                            n += 1;
+                        }
+                    }
+                }
+            }
+        }
+        n
+    }
+
+    /// Save the table of the given material in a directory
+    #[cfg(feature = "std")]
+    pub fn save_dir(&self, path: &Path, material: &str) -> Result<(), String> {
+        let bytes = self.save(material).ok_or(format!("missing tablebase '{}'", material))?;
+        fs::create_dir_all(path).map_err(|e| e.to_string())?;
+        let file = path.join(format!("{}.{}", material, TB_EXTENSION));
+        fs::write(file, bytes).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::FEN;
+
+    #[test]
+    fn test_parse_material() {
+        assert_eq!(parse_material("KQvK"), Ok("KQvK".into()));
+        assert_eq!(parse_material("kvkq"), Ok("KQvK".into()));
+        assert_eq!(parse_material("KRvKQ"), Ok("KQvKR".into()));
+        assert_eq!(parse_material("KPRvK"), Ok("KRPvK".into()));
+        assert!(parse_material("KvK").is_err());
+        assert!(parse_material("KQQQvK").is_err());
+        assert!(parse_material("QvK").is_err());
+    }
+
+    #[test]
+    fn test_material_children() {
+        assert_eq!(material_children("KQvK"), Vec::<String>::new());
+        assert_eq!(material_children("KPvK"), vec!["KBvK", "KNvK", "KQvK", "KRvK"]);
+        assert_eq!(material_children("KQvKR"), vec!["KQvK", "KRvK"]);
+    }
+
+    #[test]
+    fn test_tablebases_materials() {
+        let materials = tablebases_materials();
+        assert_eq!(materials.len(), 35);
+        assert_eq!(materials[0], "KBvK");
+        assert!(materials.contains(&"KQvKR".to_string()));
+        assert!(!materials.contains(&"KRvKQ".to_string()));
+    }
+
+    #[test]
+    fn test_material_signature() {
+        let game = Game::from_fen("8/8/3k4/8/1r6/8/3QK3/8 w - - 0 1").unwrap();
+        let white = side_signature(&game, WHITE);
+        let black = side_signature(&game, BLACK);
+        assert_eq!(white | (black << 32), material_signature("KQvKR"));
+        assert_ne!(black | (white << 32), material_signature("KQvKR"));
+    }
+
+    #[test]
+    fn test_tablebase_index() {
+        let tb = Tablebase::new("KRvK");
+        for index in 0..tb.len() {
+            let (squares, side) = tb.position(index);
+            assert_eq!(tb.index(&squares, side), index);
+        }
+
+        // Mirrored positions share the same index
+        let i = tb.index(&[A1, B2, H8], WHITE);
+        assert_eq!(tb.index(&[H1, G2, A8], WHITE), i);
+        assert_eq!(tb.index(&[A8, B7, H1], WHITE), i);
+        assert_eq!(tb.index(&[H8, G7, A1], WHITE), i);
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut tablebases = Tablebases::new();
+        assert_eq!(tablebases.generate("KRvK"), Ok(vec!["KRvK".into()]));
+        assert_eq!(tablebases.generate("KRvK"), Ok(vec![]));
+
+        // The longest mate with a rook is in 16 moves
+        let values = &tablebases.tables[&material_signature("KRvK")].values;
+        let longest = values.iter().filter_map(|&b| match Dtm::from_byte(b) {
+            Dtm::Win(d) => Some(d),
+            _ => None,
+        }).max();
+        assert_eq!(longest, Some(31));
+
+        let game = Game::from_fen("7k/8/6K1/8/8/8/8/R7 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe(&game), Some(Dtm::Win(1)));
+
+        let game = Game::from_fen("k7/2K5/8/8/8/8/8/1R6 b - - 0 1").unwrap();
+        assert_eq!(tablebases.probe(&game), Some(Dtm::Loss(2)));
+
+        // Colors are swapped for the black rook
+        let game = Game::from_fen("1r6/8/8/8/8/8/2k5/K7 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe(&game), Some(Dtm::Loss(2)));
+
+        // Stalemate
+        let game = Game::from_fen("k7/8/K7/8/8/8/8/1R6 b - - 0 1").unwrap();
+        assert_eq!(tablebases.probe(&game), Some(Dtm::Draw));
+
+        // Bare kings
+        let game = Game::from_fen("k7/8/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(tablebases.probe(&game), Some(Dtm::Draw));
+
+        // Missing table
+
+        let game = Game::from_fen("k7/8/1K6/8/8/8/8/1Q6 b - - 0 1").unwrap();
+        assert_eq!(tablebases.probe(&game), None);
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let mut tablebases = Tablebases::new();
+        tablebases.generate("KQvK").unwrap();
+        let bytes = tablebases.save("KQvK").unwrap();
+        assert!(bytes.len() < tablebases.tables[&material_signature("KQvK")].len());
+
+        let mut loaded = Tablebases::new();
+        assert_eq!(loaded.load(&bytes), Ok("KQvK".into()));
+        assert_eq!(loaded.tables[&material_signature("KQvK")].values, tablebases.tables[&material_signature("KQvK")].values);
+
+        assert!(loaded.load(&bytes[0..bytes.len() - 1]).is_err());
+        assert!(loaded.load(b"LWTB").is_err());
+    }
+}
@@ -50,6 +50,21 @@ impl Transposition {
         self.score
     }
 
+    /// Get the score of the entry for a node at the given ply
+    ///
+    /// Mate scores are stored relative to the node of the entry and need to
+    /// be made relative to the root again.
+    pub fn score_at(&self, ply: usize) -> Score {
+        let inf = INF - (MAX_PLY as Score);
+        if self.score > inf {
+            self.score - (ply as Score)
+        } else if self.score < -inf {
+            self.score + (ply as Score)
+        } else {
+            self.score
+        }
+    }
+
     pub fn best_move(&self) -> PieceMove {
         self.best_move
     }
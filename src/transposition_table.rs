@@ -62,7 +62,20 @@ impl TranspositionTable {
         }
     }
 
-    pub fn set(&mut self, hash: u64, depth: Depth, score: Score, best_move: PieceMove, bound: Bound) {
+    /// Store the result of the search of the node at the given ply
+    ///
+    /// Mate scores are relative to the root of the search, so they are made
+    /// relative to the node to be valid in any other path leading to it.
+    pub fn set(&mut self, hash: u64, depth: Depth, score: Score, best_move: PieceMove, bound: Bound, ply: usize) {
+        let inf = INF - (MAX_PLY as Score);
+        let score = if score > inf {
+            score + (ply as Score)
+        } else if score < -inf {
+            score - (ply as Score)
+        } else {
+            score
+        };
+
         let age = self.age;
         let h = self.entries.get();
         let n = self.len() as u64;
@@ -85,6 +98,7 @@ impl TranspositionTable {
     pub fn clear(&mut self) {
         let n = self.len();
         self.entries = Arc::new(SharedTable::with_capacity(n));
+        self.age = 0;
         self.clear_stats();
     }
 
@@ -103,6 +117,17 @@ impl TranspositionTable {
         self.len() * mem::size_of::<Transposition>()
     }
 
+    /// Estimate the permill of entries used by the current search from a
+    /// sample of the table
+    pub fn hashfull(&self) -> usize {
+        let sample = &self.entries.get()[..self.len().min(1000)];
+        if sample.is_empty() {
+            return 0;
+        }
+        let n = sample.iter().filter(|t| !t.best_move().is_null() && t.age() == self.age).count();
+        n * 1000 / sample.len()
+    }
+
     /// Print transposition table stats
     #[cfg(feature = "std")]
     pub fn print_stats(&mut self) {
@@ -221,7 +246,7 @@ mod tests {
         assert_eq!(t.score(), s);
         assert_eq!(t.depth(), d);
 
-        tt.set(h, d, s, m, b);
+        tt.set(h, d, s, m, b, 0);
 
         assert_eq!(tt.get(h).unwrap().best_move(), m);
 
@@ -229,6 +254,48 @@ mod tests {
         assert_eq!(tt.get(h), None);
     }
 
+    #[test]
+    fn test_transposition_table_mate_scores() {
+        let mut tt = TranspositionTable::with_capacity(1 << 20);
+
+        let h = 42;
+        let m = PieceMove::new(E2, E4, DOUBLE_PAWN_PUSH);
+        let b = Bound::Exact;
+
+        // Mated at ply 5 in a node at ply 3
+        tt.set(h, 8, -INF + 5, m, b, 3);
+        let t = tt.get(h).unwrap();
+        assert_eq!(t.score(), -INF + 2);
+        assert_eq!(t.score_at(3), -INF + 5);
+        assert_eq!(t.score_at(7), -INF + 9);
+
+        // Mating at ply 5 in a node at ply 2
+        tt.set(h, 8, INF - 5, m, b, 2);
+        let t = tt.get(h).unwrap();
+        assert_eq!(t.score(), INF - 3);
+        assert_eq!(t.score_at(4), INF - 7);
+
+        // Other scores are not modified
+        tt.set(h, 8, 100, m, b, 2);
+        assert_eq!(tt.get(h).unwrap().score_at(4), 100);
+    }
+
+    #[test]
+    fn test_transposition_table_hashfull() {
+        let mut tt = TranspositionTable::with_capacity(1 << 10);
+        assert_eq!(tt.hashfull(), 0);
+
+        let m = PieceMove::new(E2, E4, DOUBLE_PAWN_PUSH);
+        for h in 0..100 {
+            tt.set(h, 8, 100, m, Bound::Exact, 0);
+        }
+        assert_eq!(tt.hashfull(), 100);
+
+        // Entries of previous searches are not counted
+        tt.reset();
+        assert_eq!(tt.hashfull(), 0);
+    }
+
     #[cfg(feature = "std")]
     #[test]
     fn test_transposition_table_in_threads() {
@@ -249,7 +316,7 @@ mod tests {
 
             children.push(thread::spawn(move || {
                 if i == 0 {
-                    tt.set(h, d, s, m, b); // First thread set a value in TT
+                    tt.set(h, d, s, m, b, 0); // First thread set a value in TT
                 }
                 c.wait(); // Synchronize all threads
                 tt.get(h).unwrap().best_move() // All threads should get it
@@ -0,0 +1,285 @@
+use std::prelude::v1::*;
+use std::cmp;
+
+use crate::attack::Attack;
+use crate::clock::Clock;
+use crate::color::*;
+use crate::common::*;
+use crate::eval_params::EvalParams;
+use crate::fen::FEN;
+use crate::game::Game;
+use crate::pgn::{PGN, LoadPGN};
+use crate::piece_move_generator::PieceMoveGenerator;
+use crate::search::Search;
+
+// Number of plies at the beginning of a game that are too close to the
+// opening books to be used
+const OPENING_PLIES: usize = 8;
+
+// Size of the transposition table used by the quiescence searches, kept small
+// because it is cleared before each position
+const TUNER_TT_SIZE: usize = 64 << 10; // 64 KB
+
+/// A position labeled with the result of its game
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabeledPosition {
+    pub fen: String,
+
+    /// Result of the game from white, 1.0 for a win, 0.5 for a draw, and 0.0
+    /// for a loss
+    pub result: f64,
+}
+
+fn parse_result(result: &str) -> Option<f64> {
+    match result {
+        "1-0"     => Some(1.0),
+        "0-1"     => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _         => None
+    }
+}
+
+/// Read positions from EPD lines with a result given by a `c9` opcode, like
+/// `c9 "1-0";`, and ignore the other lines
+pub fn positions_from_epd(epd: &str) -> Vec<LabeledPosition> {
+    let mut positions = Vec::new();
+    for line in epd.lines() {
+        if let Some(i) = line.find(" c9 ") {
+            let fen = line[..i].split_whitespace().take(6).collect::<Vec<_>>().join(" ");
+            let result = line[(i + 4)..].trim().trim_end_matches(';').trim_matches('"');
+            if let Some(result) = parse_result(result) {
+                positions.push(LabeledPosition { fen, result });
+            }
+        }
+    }
+    positions
+}
+
+/// Read the positions of the games of a PGN file labeled with their results,
+/// without the positions of the opening and the positions in check
+pub fn positions_from_pgn(pgn: &str) -> Vec<LabeledPosition> {
+    // Split the games at the first header following a body
+    let mut games = Vec::new();
+    let mut game = String::new();
+    let mut has_body = false;
+    for line in pgn.lines() {
+        let is_header = line.starts_with('[');
+        if is_header && has_body {
+            games.push(game);
+            game = String::new();
+            has_body = false;
+        }
+        if !is_header && !line.trim().is_empty() {
+            has_body = true;
+        }
+        game.push_str(line);
+        game.push('\n');
+    }
+    if has_body {
+        games.push(game);
+    }
+
+    let mut positions = Vec::new();
+    let mut board = Game::new();
+    board.tt_resize(TUNER_TT_SIZE);
+    for game in games {
+        let pgn = PGN::from(game);
+        let result = match parse_result(&pgn.result()) {
+            Some(result) => result,
+            None => continue,
+        };
+        board.load_pgn(pgn);
+
+        // Undo the moves of the game to get back its positions
+        let mut history = board.history.clone();
+        while let Some(m) = history.pop() {
+            if history.len() >= OPENING_PLIES && !board.is_check(board.side()) {
+                positions.push(LabeledPosition { fen: board.to_fen(), result });
+            }
+            board.undo_move(m);
+        }
+    }
+    positions
+}
+
+/// Tuner of evaluation parameters with the Texel method
+///
+/// The quiescence search scores of the positions are mapped to expected
+/// results with a logistic function, and the parameters are changed one at
+/// a time as long as they reduce the mean squared error between these
+/// expected results and the results of the games.
+pub struct Tuner {
+    pub params: EvalParams,
+
+    /// Scaling constant of the logistic function
+    pub k: f64,
+
+    positions: Vec<LabeledPosition>,
+    game: Game,
+}
+
+impl Tuner {
+    /// Create a new `Tuner` of the given parameters with the given positions
+    pub fn new(params: EvalParams, positions: Vec<LabeledPosition>) -> Tuner {
+        let mut game = Game::new();
+        game.tt_resize(TUNER_TT_SIZE);
+        game.clock = Clock::new(1, u64::MAX);
+        Tuner { params, k: 1.0, positions, game }
+    }
+
+    /// Get the number of positions
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Check if the tuner has no positions
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Get the quiescence search scores of the positions from white with the
+    /// given parameters
+    pub fn scores(&mut self, params: &EvalParams) -> Vec<Score> {
+        self.game.set_eval_params(params.clone());
+        let mut scores = Vec::with_capacity(self.positions.len());
+        for position in &self.positions {
+            self.game.load_fen(&position.fen).unwrap();
+            self.game.clock.start(self.game.positions.len());
+            self.game.nodes_count = 0;
+            let score = self.game.quiescence(-INF, INF, 0, 0);
+            scores.push(if self.game.side() == WHITE { score } else { -score });
+        }
+        scores
+    }
+
+    /// Get the mean squared error of the given scores with the results of
+    /// the positions
+    pub fn error(&self, scores: &[Score], k: f64) -> f64 {
+        let mut sum = 0.0;
+        for (position, &score) in self.positions.iter().zip(scores) {
+            let expected = 1.0 / (1.0 + 10f64.powf(-k * score as f64 / 400.0));
+            sum += (position.result - expected).powi(2);
+        }
+        sum / cmp::max(scores.len(), 1) as f64
+    }
+
+    /// Find the scaling constant minimizing the error with the current
+    /// parameters
+    pub fn optimize_k(&mut self) -> f64 {
+        let scores = self.scores(&self.params.clone());
+        let mut step = 0.5;
+        let mut best_error = self.error(&scores, self.k);
+        while step > 0.001 {
+            let mut improved = false;
+            for &k in &[self.k + step, self.k - step] {
+                if k <= 0.0 {
+                    continue;
+                }
+                let error = self.error(&scores, k);
+                if error < best_error {
+                    best_error = error;
+                    self.k = k;
+                    improved = true;
+                    break;
+                }
+            }
+            if !improved {
+                step /= 2.0;
+            }
+        }
+        self.k
+    }
+
+    /// Run one pass of local search over every parameter and return the new
+    /// error, or `None` if no parameter could be improved
+    pub fn tune_iteration(&mut self) -> Option<f64> {
+        let k = self.k;
+        let scores = self.scores(&self.params.clone());
+        let mut best_error = self.error(&scores, k);
+        let mut improved = false;
+        let n = self.params.clone().params_mut().iter().map(|(_, _, values)| values.len()).sum();
+        for i in 0..n {
+            for &delta in &[1, -1] {
+                let mut params = self.params.clone();
+                if let Some(value) = param_mut(&mut params, i) {
+                    *value += delta;
+                }
+                let scores = self.scores(&params);
+                let error = self.error(&scores, k);
+                if error < best_error {
+                    best_error = error;
+                    self.params = params;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if improved { Some(best_error) } else { None }
+    }
+}
+
+// Get the parameter at the given index in the values of all the parameters
+fn param_mut(params: &mut EvalParams, mut i: usize) -> Option<&mut Score> {
+    for (_, _, values) in params.params_mut() {
+        if i < values.len() {
+            return Some(&mut values[i]);
+        }
+        i -= values.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions_from_epd() {
+        let epd = "\
+            rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - c9 \"1-0\";\n\
+            8/8/4k3/8/8/4K3/8/8 w - - c9 \"1/2-1/2\";\n\
+            8/8/4k3/8/8/4K3/8/8 w - - bm Kd3;\n";
+        let positions = positions_from_epd(epd);
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].fen, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -");
+        assert_eq!(positions[0].result, 1.0);
+        assert_eq!(positions[1].result, 0.5);
+    }
+
+    #[test]
+    fn test_positions_from_pgn() {
+        let pgn = "\
+            [Event \"?\"]\n\
+            [Result \"0-1\"]\n\
+            \n\
+            1. f3 e5 2. g4 Qh4# 0-1\n\
+            \n\
+            [Event \"?\"]\n\
+            [Result \"1-0\"]\n\
+            \n\
+            1. e4 e5 2. Nf3 Nc6 3. Bc4 Nd4 4. Nxe5 Qg5 5. Nxf7 Qxg2 6. Rf1 Qxe4+ 7. Be2 Nf3# 1-0\n";
+        let positions = positions_from_pgn(pgn);
+
+        // Positions after the opening of the second game not in check
+        assert_eq!(positions.len(), 4);
+        assert!(positions.iter().all(|p| p.result == 1.0));
+    }
+
+    #[test]
+    fn test_tuner() {
+        // The side up a pawn always wins
+        let mut positions = Vec::new();
+        for _ in 0..4 {
+            positions.push(LabeledPosition { fen: "4k3/pp6/8/8/8/8/PPP5/4K3 w - - 0 1".into(), result: 1.0 });
+            positions.push(LabeledPosition { fen: "4k3/ppp5/8/8/8/8/PP6/4K3 w - - 0 1".into(), result: 0.0 });
+        }
+        let mut tuner = Tuner::new(EvalParams::default(), positions);
+        assert_eq!(tuner.len(), 8);
+
+        let scores = tuner.scores(&tuner.params.clone());
+        let error = tuner.error(&scores, tuner.k);
+        let tuned_error = tuner.tune_iteration().unwrap();
+        assert!(tuned_error < error);
+        assert!(tuner.params != EvalParams::default());
+    }
+}
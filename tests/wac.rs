@@ -2,11 +2,11 @@ extern crate littlewing;
 
 use std::fs;
 
-use littlewing::clock::Clock;
 use littlewing::fen::FEN;
 use littlewing::game::Game;
 use littlewing::piece_move_notation::PieceMoveNotation;
 use littlewing::search::Search;
+use littlewing::search_limits::SearchLimits;
 
 #[test]
 fn test_wac() {
@@ -29,9 +29,13 @@ fn test_wac() {
         assert_eq!(mt, "bm");
 
         game.load_fen(fen).unwrap();
-        game.clock = Clock::new(1, 1000); // search for 1 second
+        let limits = SearchLimits {
+            nodes: Some(500_000), // search 500k nodes the same way every time
+            deterministic: true,
+            ..SearchLimits::new()
+        };
 
-        let m = game.search(1..99).unwrap();
+        let m = game.search(limits).unwrap();
         let best_move = game.move_to_san(m);
 
         println!("{} <- {}", moves, best_move);